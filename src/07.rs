@@ -1,6 +1,9 @@
 use anyhow::{bail, Context, Result};
 use itertools::Itertools;
-use std::{cmp::min, collections::HashMap};
+use std::{
+    cmp::{max, min},
+    collections::HashMap,
+};
 
 type FileTree = HashMap<String, FileEntry>;
 
@@ -63,7 +66,7 @@ fn sum_dir_size(tree: &FileTree) -> usize {
 }
 
 fn find_smallest_dir_size(tree: &FileTree, min_size: usize) -> usize {
-    tree.iter().fold(std::usize::MAX, |best, (_, e)| match e {
+    tree.iter().fold(usize::MAX, |best, (_, e)| match e {
         FileEntry::Directory { files } => {
             let dir_size = e.get_size();
             if dir_size >= min_size {
@@ -76,9 +79,151 @@ fn find_smallest_dir_size(tree: &FileTree, min_size: usize) -> usize {
     })
 }
 
+/// A set of non-nested directories whose deletion frees at least the requested space
+struct Plan {
+    dirs: Vec<String>,
+    freed: usize,
+}
+
+/// Directory tree flattened in pre-order, so that a directory's descendants are stored
+/// right after it, up to (excluding) `end`
+struct FlatDir {
+    path: String,
+    size: usize,
+    end: usize,
+    // the parent contains nothing else, so deleting it frees the same space
+    same_as_parent: bool,
+}
+
+fn flatten_dirs(tree: &FileTree, path: &str, parent_size: Option<usize>, dirs: &mut Vec<FlatDir>) {
+    // sort entries so that plans are listed in a deterministic order
+    for (name, e) in tree.iter().sorted_by(|(n1, _), (n2, _)| n1.cmp(n2)) {
+        if let FileEntry::Directory { files } = e {
+            let idx = dirs.len();
+            let path = format!("{path}/{name}");
+            let size = e.get_size();
+            dirs.push(FlatDir {
+                path: path.clone(),
+                size,
+                end: 0,
+                same_as_parent: parent_size == Some(size),
+            });
+            flatten_dirs(files, &path, Some(size), dirs);
+            dirs[idx].end = dirs.len();
+        }
+    }
+}
+
+fn space_to_free(root: &FileTree, disk_size: usize, required_space: usize) -> usize {
+    let used_space = root.iter().fold(0, |acc, (_, e)| acc + e.get_size());
+    let remaining_space = disk_size.saturating_sub(used_space);
+    required_space.saturating_sub(remaining_space)
+}
+
+struct Planner {
+    dirs: Vec<FlatDir>,
+    // max_freeable[i] is the most space that can be freed with directories i..
+    max_freeable: Vec<usize>,
+    to_free: usize,
+    max_dirs: usize,
+    max_plans: usize,
+    plans: Vec<Plan>,
+}
+
+impl Planner {
+    fn search(&mut self, i: usize, selected: &mut Vec<usize>, freed: usize) {
+        if freed >= self.to_free {
+            // adding more directories would only waste more space
+            self.plans.push(Plan {
+                dirs: selected
+                    .iter()
+                    .map(|&d| self.dirs[d].path.clone())
+                    .collect_vec(),
+                freed,
+            });
+            self.plans.sort_by_key(|p| (p.freed, p.dirs.len()));
+            self.plans.truncate(self.max_plans);
+            return;
+        }
+        if i == self.dirs.len()
+            || selected.len() == self.max_dirs
+            || freed + self.max_freeable[i] < self.to_free
+        {
+            return;
+        }
+        if self.plans.len() == self.max_plans && freed >= self.plans[self.max_plans - 1].freed {
+            return;
+        }
+        // delete directory i (and thus all its subdirectories), unless the same plan is
+        // already found with its parent
+        if !self.dirs[i].same_as_parent {
+            selected.push(i);
+            self.search(self.dirs[i].end, selected, freed + self.dirs[i].size);
+            selected.pop();
+        }
+        // keep directory i
+        self.search(i + 1, selected, freed);
+    }
+}
+
+/// Find the (at most `max_plans`) sets of up to `max_dirs` non-nested directories that free
+/// enough space for `required_space` on a disk of `disk_size`, ranked by wasted space.
+/// Plans are only optimal among sets of at most `max_dirs` directories: the search is
+/// exhaustive, and its cost grows exponentially with `max_dirs`.
+fn plan_free_space(
+    root: &FileTree,
+    disk_size: usize,
+    required_space: usize,
+    max_dirs: usize,
+    max_plans: usize,
+) -> Vec<Plan> {
+    let mut dirs = Vec::new();
+    flatten_dirs(root, "", None, &mut dirs);
+
+    // deleting a directory always frees more than deleting any of its subdirectories
+    let mut max_freeable = vec![0; dirs.len() + 1];
+    for i in (0..dirs.len()).rev() {
+        max_freeable[i] = max(
+            max_freeable[i + 1],
+            dirs[i].size + max_freeable[dirs[i].end],
+        );
+    }
+
+    let mut planner = Planner {
+        dirs,
+        max_freeable,
+        to_free: space_to_free(root, disk_size, required_space),
+        max_dirs,
+        max_plans,
+        plans: Vec::new(),
+    };
+    if max_plans > 0 {
+        planner.search(0, &mut Vec::new(), 0);
+    }
+    planner.plans
+}
+
 const INPUT: &str = include_str!("../data/07.txt");
+const DISK_SIZE: usize = 70_000_000;
+const REQUIRED_SPACE: usize = 30_000_000;
 
 fn main() -> Result<()> {
+    // usage: 07 [--max-dirs N] [--plans N]
+    // list the N best free-space plans (5 by default) among the sets of at most
+    // --max-dirs directories (3 by default)
+    let args = std::env::args().skip(1).collect_vec();
+    let option = |name: &str, default: usize| -> Result<usize> {
+        match args.iter().position(|a| a == name) {
+            Some(i) => Ok(args
+                .get(i + 1)
+                .with_context(|| format!("{name} requires a value"))?
+                .parse()?),
+            None => Ok(default),
+        }
+    };
+    let max_dirs = option("--max-dirs", 3)?;
+    let max_plans = option("--plans", 5)?;
+
     // part 1
     let mut root: FileTree = HashMap::new();
     let mut curr_path: Vec<String> = Vec::new();
@@ -117,12 +262,20 @@ fn main() -> Result<()> {
     println!("{total_size}");
 
     // part 2
-    let remaining_space = 70_000_000 - root.iter().fold(0, |acc, (_, e)| acc + e.get_size());
-    let required_space = 30_000_000;
-    let space_to_free = required_space - remaining_space;
-
+    let space_to_free = space_to_free(&root, DISK_SIZE, REQUIRED_SPACE);
     let dir_size_to_free = find_smallest_dir_size(&root, space_to_free);
     println!("{dir_size_to_free}");
 
+    // free-space planner
+    println!("best plans with at most {max_dirs} directories:");
+    for plan in plan_free_space(&root, DISK_SIZE, REQUIRED_SPACE, max_dirs, max_plans) {
+        println!(
+            "free {} (wasted {}): {}",
+            plan.freed,
+            plan.freed - space_to_free,
+            plan.dirs.join(" ")
+        );
+    }
+
     Ok(())
 }
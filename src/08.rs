@@ -1,63 +1,196 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use itertools::Itertools;
 
-const INPUT: &str = include_str!("../data/08.txt");
+type Forest = Vec<Vec<u32>>;
 
-fn main() -> Result<()> {
-    // part 1
+fn parse_forest(input: &str) -> Forest {
     let mut forest_matrix = Vec::new();
-    for line in INPUT.lines() {
+    for line in input.lines() {
         let line = line.chars().map(|c| c.to_digit(10).unwrap()).collect_vec();
         forest_matrix.push(line);
     }
-    let forest_matrix = forest_matrix;
+    forest_matrix
+}
+
+/// All the lines of sight through the forest: every row and column, in both directions.
+/// Each line lists tree coordinates in the order they are seen from the edge.
+fn lines_of_sight(rows: usize, cols: usize) -> impl Iterator<Item = Vec<(usize, usize)>> {
+    let from_left = (0..rows).map(move |i| (0..cols).map(|j| (i, j)).collect_vec());
+    let from_right = (0..rows).map(move |i| (0..cols).rev().map(|j| (i, j)).collect_vec());
+    let from_top = (0..cols).map(move |j| (0..rows).map(|i| (i, j)).collect_vec());
+    let from_bottom = (0..cols).map(move |j| (0..rows).rev().map(|i| (i, j)).collect_vec());
+    from_left
+        .chain(from_right)
+        .chain(from_top)
+        .chain(from_bottom)
+}
 
+/// For each tree, whether it is visible from outside the forest
+fn visible_mask(forest: &Forest) -> Vec<Vec<bool>> {
     // assume input is a valid matrix
-    let rows = forest_matrix.len();
-    let cols = forest_matrix[0].len();
-
-    let mut nb_visible = 0;
-    for i in 0..rows {
-        for j in 0..cols {
-            let height = forest_matrix[i][j];
-            let visible_from_top = (0..i).all(|k| forest_matrix[k][j] < height);
-            let visible_from_bottom = (i + 1..rows).all(|k| forest_matrix[k][j] < height);
-            let visible_from_left = (0..j).all(|k| forest_matrix[i][k] < height);
-            let visible_from_right = (j + 1..cols).all(|k| forest_matrix[i][k] < height);
-            if visible_from_top || visible_from_bottom || visible_from_left || visible_from_right {
-                nb_visible += 1;
+    let rows = forest.len();
+    let cols = forest[0].len();
+
+    let mut visible = vec![vec![false; cols]; rows];
+    for line in lines_of_sight(rows, cols) {
+        // a tree is visible if it is strictly higher than all trees before it
+        let mut highest = None;
+        for (i, j) in line {
+            let height = forest[i][j];
+            if highest.is_none_or(|h| height > h) {
+                visible[i][j] = true;
+                highest = Some(height);
             }
         }
     }
-    println!("{nb_visible}");
+    visible
+}
 
-    // part 2
-    let mut highest_score = 0;
-    for i in 1..rows - 1 {
-        for j in 1..cols - 1 {
-            let height = forest_matrix[i][j];
-            let from_top = (1..i)
-                .rev()
-                .take_while(|&k| forest_matrix[k][j] < height)
-                .count()
-                + 1;
-            let from_bottom = (i + 1..rows - 1)
-                .take_while(|&k| forest_matrix[k][j] < height)
-                .count()
-                + 1;
-            let from_left = (1..j)
-                .rev()
-                .take_while(|&k| forest_matrix[i][k] < height)
-                .count()
-                + 1;
-            let from_right = (j + 1..cols - 1)
-                .take_while(|&k| forest_matrix[i][k] < height)
-                .count()
-                + 1;
-            let score = from_top * from_bottom * from_left * from_right;
-            highest_score = std::cmp::max(highest_score, score);
+/// For each tree, the product of its viewing distances in the 4 directions
+fn scenic_scores(forest: &Forest) -> Vec<Vec<usize>> {
+    // assume input is a valid matrix
+    let rows = forest.len();
+    let cols = forest[0].len();
+
+    let mut scores = vec![vec![1; cols]; rows];
+    for line in lines_of_sight(rows, cols) {
+        // monotonic stack of (position, height) of the trees that can still block the view
+        let mut blockers: Vec<(usize, u32)> = Vec::new();
+        for (pos, (i, j)) in line.into_iter().enumerate() {
+            let height = forest[i][j];
+            while blockers.last().is_some_and(|&(_, h)| h < height) {
+                blockers.pop();
+            }
+            // looking back, the view stops at the first tree at least as high, or at the edge
+            let distance = match blockers.last() {
+                Some(&(blocker_pos, _)) => pos - blocker_pos,
+                None => pos,
+            };
+            scores[i][j] *= distance;
+            blockers.push((pos, height));
+        }
+    }
+    scores
+}
+
+/// Map `t` in [0, 1] to a black -> red -> yellow -> white color ramp
+fn heat_color(t: f64) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0) * 3.0;
+    let channel = |x: f64| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(t), channel(t - 1.0), channel(t - 2.0)]
+}
+
+/// Scores span several orders of magnitude, so they are normalized on a log scale
+fn normalized_scores(scores: &[Vec<usize>]) -> Vec<Vec<f64>> {
+    let max_score = scores.iter().flatten().copied().max().unwrap_or(0);
+    let max_log = (max_score as f64).ln_1p();
+    scores
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&s| {
+                    if max_log > 0.0 {
+                        (s as f64).ln_1p() / max_log
+                    } else {
+                        0.0
+                    }
+                })
+                .collect_vec()
+        })
+        .collect_vec()
+}
+
+/// Encode a grid as a binary PNM image (P5 for 1 channel, P6 for 3 channels), each cell
+/// being drawn as a `scale` x `scale` square
+fn pnm_image<const C: usize>(pixels: &[Vec<[u8; C]>], scale: usize) -> Vec<u8> {
+    let magic = match C {
+        1 => "P5",
+        3 => "P6",
+        _ => panic!("unsupported number of channels: {C}"),
+    };
+    let height = pixels.len() * scale;
+    let width = pixels.first().map_or(0, |row| row.len()) * scale;
+    let mut image = format!("{magic}\n{width} {height}\n255\n").into_bytes();
+    for row in pixels {
+        for _ in 0..scale {
+            for pixel in row {
+                for _ in 0..scale {
+                    image.extend_from_slice(pixel);
+                }
+            }
         }
     }
+    image
+}
+
+fn visible_mask_pgm(visible: &[Vec<bool>], scale: usize) -> Vec<u8> {
+    let pixels = visible
+        .iter()
+        .map(|row| row.iter().map(|&v| [if v { 255 } else { 0 }]).collect_vec())
+        .collect_vec();
+    pnm_image(&pixels, scale)
+}
+
+fn scenic_scores_ppm(scores: &[Vec<usize>], scale: usize) -> Vec<u8> {
+    let pixels = normalized_scores(scores)
+        .iter()
+        .map(|row| row.iter().map(|&t| heat_color(t)).collect_vec())
+        .collect_vec();
+    pnm_image(&pixels, scale)
+}
+
+/// Render the forest heights in the terminal: visible trees are bold on a background
+/// colored by their scenic score, hidden trees are dimmed
+fn ansi_render(forest: &Forest, visible: &[Vec<bool>], scores: &[Vec<usize>]) -> String {
+    let mut out = String::new();
+    for ((heights, visible), scores) in forest.iter().zip(visible).zip(normalized_scores(scores)) {
+        for ((height, visible), t) in heights.iter().zip(visible).zip(scores) {
+            let [r, g, b] = heat_color(t);
+            let style = if *visible { 1 } else { 2 };
+            out += &format!("\x1b[{style};48;2;{r};{g};{b}m{height}");
+        }
+        out += "\x1b[0m\n";
+    }
+    out
+}
+
+const INPUT: &str = include_str!("../data/08.txt");
+const IMAGE_SCALE: usize = 4;
+
+fn main() -> Result<()> {
+    // usage: 08 [--ansi] [--images <dir>]
+    let args = std::env::args().skip(1).collect_vec();
+    let image_dir = args
+        .iter()
+        .position(|a| a == "--images")
+        .map(|i| args.get(i + 1).context("--images requires a directory"))
+        .transpose()?;
+
+    let forest_matrix = parse_forest(INPUT);
+    let visible = visible_mask(&forest_matrix);
+    let scores = scenic_scores(&forest_matrix);
+
+    if args.iter().any(|a| a == "--ansi") {
+        print!("{}", ansi_render(&forest_matrix, &visible, &scores));
+    }
+    if let Some(dir) = image_dir {
+        let dir = std::path::Path::new(dir);
+        std::fs::write(
+            dir.join("08_visible.pgm"),
+            visible_mask_pgm(&visible, IMAGE_SCALE),
+        )?;
+        std::fs::write(
+            dir.join("08_scores.ppm"),
+            scenic_scores_ppm(&scores, IMAGE_SCALE),
+        )?;
+    }
+
+    // part 1
+    let nb_visible = visible.iter().flatten().filter(|&&v| v).count();
+    println!("{nb_visible}");
+
+    // part 2
+    let highest_score = scores.into_iter().flatten().max().unwrap_or(0);
     println!("{highest_score}");
 
     Ok(())
@@ -1,6 +1,6 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use anyhow::{Context, Result};
-use std::collections::HashSet;
+use std::{collections::HashSet, fmt::Display};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Direction {
@@ -8,45 +8,83 @@ enum Direction {
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 use Direction::*;
 
-impl TryFrom<char> for Direction {
+impl Direction {
+    /// (row, col) offset of a single step
+    fn offset(&self) -> (i32, i32) {
+        match self {
+            Up => (-1, 0),
+            Down => (1, 0),
+            Left => (0, -1),
+            Right => (0, 1),
+            UpLeft => (-1, -1),
+            UpRight => (-1, 1),
+            DownLeft => (1, -1),
+            DownRight => (1, 1),
+        }
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Up => "U",
+            Down => "D",
+            Left => "L",
+            Right => "R",
+            UpLeft => "UL",
+            UpRight => "UR",
+            DownLeft => "DL",
+            DownRight => "DR",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl TryFrom<&str> for Direction {
     type Error = anyhow::Error;
 
-    fn try_from(value: char) -> std::result::Result<Self, Self::Error> {
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
         match value {
-            'U' => Ok(Up),
-            'D' => Ok(Down),
-            'L' => Ok(Left),
-            'R' => Ok(Right),
-            c => Err(anyhow!("not a valid direction: {}", c)),
+            "U" => Ok(Up),
+            "D" => Ok(Down),
+            "L" => Ok(Left),
+            "R" => Ok(Right),
+            "UL" => Ok(UpLeft),
+            "UR" => Ok(UpRight),
+            "DL" => Ok(DownLeft),
+            "DR" => Ok(DownRight),
+            s => Err(anyhow!("not a valid direction: {}", s)),
         }
     }
 }
 
 #[derive(Debug)]
-struct Rope<const N: usize> {
-    knots: [(i32, i32); N],
+struct Rope {
+    knots: Vec<(i32, i32)>,
 }
 
-impl<const N: usize> Default for Rope<N> {
-    fn default() -> Self {
-        Self { knots: [(0, 0); N] }
+impl Rope {
+    fn new(length: usize) -> Self {
+        assert!(length > 0, "a rope has at least one knot");
+        Self {
+            knots: vec![(0, 0); length],
+        }
     }
-}
 
-impl<const N: usize> Rope<N> {
     fn move_head(&mut self, direction: Direction) {
         // move head
-        match direction {
-            Up => self.knots[0].0 -= 1,
-            Down => self.knots[0].0 += 1,
-            Left => self.knots[0].1 -= 1,
-            Right => self.knots[0].1 += 1,
-        };
+        let offset = direction.offset();
+        self.knots[0].0 += offset.0;
+        self.knots[0].1 += offset.1;
         // propagate to tail
-        for i in 1..N {
+        for i in 1..self.knots.len() {
             let diff = (
                 self.knots[i - 1].0 - self.knots[i].0,
                 self.knots[i - 1].1 - self.knots[i].1,
@@ -60,49 +98,221 @@ impl<const N: usize> Rope<N> {
         }
     }
 
-    fn get_tail(&self) -> (i32, i32) {
-        *self.knots.last().unwrap()
+    fn get_knot(&self, knot: usize) -> (i32, i32) {
+        self.knots[knot]
     }
 }
 
-const INPUT: &str = include_str!("../data/09.txt");
+fn parse_moves(input: &str) -> Result<Vec<(Direction, u32)>> {
+    input
+        .lines()
+        .map(|line| {
+            let (direction, steps) = line
+                .split_once(' ')
+                .with_context(|| format!("invalid line format: {line}"))?;
+            let direction = Direction::try_from(direction)?;
+            let steps = steps.parse::<u32>()?;
+            Ok((direction, steps))
+        })
+        .collect()
+}
 
-fn main() -> Result<()> {
-    // part 1
-    let mut rope = Rope::<2>::default();
+/// Positions visited by the given knot of a rope of `length` knots
+fn visited_by_knot(moves: &[(Direction, u32)], length: usize, knot: usize) -> HashSet<(i32, i32)> {
+    let mut rope = Rope::new(length);
     let mut visited = HashSet::<(i32, i32)>::new();
-    visited.insert(rope.get_tail());
-    for line in INPUT.lines() {
-        let (direction, steps) = line
-            .split_once(' ')
-            .with_context(|| format!("invalid line format: {line}"))?;
-        let direction =
-            Direction::try_from(direction.chars().next().context("empty direction string")?)?;
-        let steps = steps.parse::<u32>()?;
+    visited.insert(rope.get_knot(knot));
+    for &(direction, steps) in moves {
         for _ in 0..steps {
             rope.move_head(direction);
-            visited.insert(rope.get_tail());
+            visited.insert(rope.get_knot(knot));
         }
     }
-    println!("{}", visited.len());
+    visited
+}
 
-    // part 2
-    let mut rope = Rope::<10>::default();
-    let mut visited = HashSet::<(i32, i32)>::new();
-    visited.insert(rope.get_tail());
-    for line in INPUT.lines() {
-        let (direction, steps) = line
-            .split_once(' ')
-            .with_context(|| format!("invalid line format: {line}"))?;
-        let direction =
-            Direction::try_from(direction.chars().next().context("empty direction string")?)?;
-        let steps = steps.parse::<u32>()?;
+/// Smallest rectangle containing a set of (row, col) positions
+#[derive(Debug, Clone, Copy)]
+struct BoundingBox {
+    min: (i32, i32),
+    max: (i32, i32),
+}
+
+impl BoundingBox {
+    fn new(pos: (i32, i32)) -> Self {
+        Self { min: pos, max: pos }
+    }
+
+    fn extend(&mut self, pos: (i32, i32)) {
+        self.min = (self.min.0.min(pos.0), self.min.1.min(pos.1));
+        self.max = (self.max.0.max(pos.0), self.max.1.max(pos.1));
+    }
+
+    fn rows(&self) -> usize {
+        (self.max.0 - self.min.0 + 1) as usize
+    }
+
+    fn cols(&self) -> usize {
+        (self.max.1 - self.min.1 + 1) as usize
+    }
+
+    /// Draw a grid covering the box, one char per position
+    fn draw(&self, mut get_char: impl FnMut((i32, i32)) -> char) -> String {
+        let mut out = String::new();
+        for row in self.min.0..=self.max.0 {
+            for col in self.min.1..=self.max.1 {
+                out.push(get_char((row, col)));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Label of a knot, as in the puzzle: H for head, then T for the tail of a 2-knot rope, or the
+/// knot number otherwise. Knots after 9 have no digit and are all shown as `*`.
+fn knot_label(knot: usize, length: usize) -> char {
+    match knot {
+        0 => 'H',
+        1 if length == 2 => 'T',
+        k => std::char::from_digit(k as u32, 10).unwrap_or('*'),
+    }
+}
+
+fn render_rope(rope: &Rope, bbox: &BoundingBox) -> String {
+    bbox.draw(|pos| {
+        // a knot hides the ones after it, and all knots hide the start
+        match rope.knots.iter().position(|&k| k == pos) {
+            Some(knot) => knot_label(knot, rope.knots.len()),
+            None if pos == (0, 0) => 's',
+            None => '.',
+        }
+    })
+}
+
+fn render_visited(visited: &HashSet<(i32, i32)>) -> String {
+    let bbox = visited_bbox(visited);
+    bbox.draw(|pos| match pos {
+        (0, 0) => 's',
+        pos if visited.contains(&pos) => '#',
+        _ => '.',
+    })
+}
+
+fn visited_bbox(visited: &HashSet<(i32, i32)>) -> BoundingBox {
+    visited
+        .iter()
+        .fold(BoundingBox::new((0, 0)), |mut bbox, &pos| {
+            bbox.extend(pos);
+            bbox
+        })
+}
+
+/// Encode visited positions as a binary PPM image: visited in white, start in red
+fn visited_ppm(visited: &HashSet<(i32, i32)>) -> Vec<u8> {
+    let bbox = visited_bbox(visited);
+    let mut image = format!("P6\n{} {}\n255\n", bbox.cols(), bbox.rows()).into_bytes();
+    for row in bbox.min.0..=bbox.max.0 {
+        for col in bbox.min.1..=bbox.max.1 {
+            let pixel = match (row, col) {
+                (0, 0) => [255, 0, 0],
+                pos if visited.contains(&pos) => [255, 255, 255],
+                _ => [0, 0, 0],
+            };
+            image.extend_from_slice(&pixel);
+        }
+    }
+    image
+}
+
+/// Render the rope after each instruction, in the puzzle's format. All the frames share the
+/// bounding box of the whole motion.
+fn render_motion(moves: &[(Direction, u32)], length: usize) -> String {
+    let mut rope = Rope::new(length);
+    let mut bbox = BoundingBox::new((0, 0));
+    for &(direction, steps) in moves {
         for _ in 0..steps {
             rope.move_head(direction);
-            visited.insert(rope.get_tail());
+            rope.knots.iter().for_each(|&k| bbox.extend(k));
         }
     }
-    println!("{}", visited.len());
+
+    let mut rope = Rope::new(length);
+    let mut out = format!("== Initial State ==\n\n{}\n", render_rope(&rope, &bbox));
+    for &(direction, steps) in moves {
+        for _ in 0..steps {
+            rope.move_head(direction);
+        }
+        out += &format!(
+            "== {direction} {steps} ==\n\n{}\n",
+            render_rope(&rope, &bbox)
+        );
+    }
+    out
+}
+
+const INPUT: &str = include_str!("../data/09.txt");
+
+fn main() -> Result<()> {
+    let moves = parse_moves(INPUT)?;
+
+    // usage: 09 [--render] [--trail-image FILE] [MIN_LENGTH[..MAX_LENGTH]] [KNOT]
+    // print the number of positions visited by KNOT (default: tail) for each rope length
+    // --render shows knots 1 to 9 by their number and the following ones as *
+    let mut render = false;
+    let mut trail_image = None;
+    let mut positionals = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--render" => render = true,
+            "--trail-image" => {
+                trail_image = Some(args.next().context("--trail-image requires a file")?)
+            }
+            _ => positionals.push(arg),
+        }
+    }
+
+    if render || trail_image.is_some() {
+        // debug the rope of part 2, unless another length is given
+        let length = match positionals.first() {
+            Some(length) => length.parse::<usize>()?.max(1),
+            None => 10,
+        };
+        let visited = visited_by_knot(&moves, length, length - 1);
+        if render {
+            print!("{}", render_motion(&moves, length));
+            println!("== Tail trail ==\n\n{}", render_visited(&visited));
+        }
+        if let Some(file) = trail_image {
+            std::fs::write(file, visited_ppm(&visited))?;
+        }
+        return Ok(());
+    }
+
+    let mut args = positionals.into_iter();
+    if let Some(lengths) = args.next() {
+        let (min_length, max_length) = match lengths.split_once("..") {
+            Some((min, max)) => (min.parse::<usize>()?, max.parse::<usize>()?),
+            None => (lengths.parse::<usize>()?, lengths.parse::<usize>()?),
+        };
+        let knot = args.next().map(|k| k.parse::<usize>()).transpose()?;
+        for length in min_length.max(1)..=max_length {
+            let knot = knot.unwrap_or(length - 1);
+            if knot >= length {
+                bail!("knot {knot} does not exist in a rope of length {length}");
+            }
+            let visited = visited_by_knot(&moves, length, knot);
+            println!("{length} {}", visited.len());
+        }
+        return Ok(());
+    }
+
+    // part 1
+    println!("{}", visited_by_knot(&moves, 2, 1).len());
+
+    // part 2
+    println!("{}", visited_by_knot(&moves, 10, 9).len());
 
     Ok(())
 }
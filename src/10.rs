@@ -1,50 +1,679 @@
 use anyhow::{bail, Context, Result};
+use std::io::{BufRead, Write};
+
+mod asm {
+    use anyhow::{bail, Context, Result};
+    use std::{collections::HashMap, fmt::Display};
+
+    pub const MAX_OPERANDS: usize = 2;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum OperandType {
+        // signed integer literal
+        Int,
+        // jump target: a label or an instruction index
+        Label,
+    }
+
+    /// Description of an instruction of the CPU
+    #[derive(Debug)]
+    pub struct InstructionSpec {
+        pub opcode: &'static str,
+        pub operands: &'static [OperandType],
+        // number of cycles to complete the instruction
+        pub cycles: usize,
+        // applied to the X and PC registers when the instruction completes
+        pub execute: fn(x: &mut i32, pc: &mut usize, operands: &[i32]),
+    }
+
+    /// Instructions supported by the CPU. Adding a row is enough to support a new instruction.
+    pub static INSTRUCTION_SET: &[InstructionSpec] = &[
+        InstructionSpec {
+            opcode: "noop",
+            operands: &[],
+            cycles: 1,
+            execute: |_, _, _| {},
+        },
+        InstructionSpec {
+            opcode: "addx",
+            operands: &[OperandType::Int],
+            cycles: 2,
+            execute: |x, _, ops| *x += ops[0],
+        },
+        InstructionSpec {
+            opcode: "subx",
+            operands: &[OperandType::Int],
+            cycles: 2,
+            execute: |x, _, ops| *x -= ops[0],
+        },
+        InstructionSpec {
+            opcode: "jmp",
+            operands: &[OperandType::Label],
+            cycles: 1,
+            execute: |_, pc, ops| *pc = ops[0] as usize,
+        },
+        InstructionSpec {
+            opcode: "jz",
+            operands: &[OperandType::Label],
+            cycles: 1,
+            execute: |x, pc, ops| {
+                if *x == 0 {
+                    *pc = ops[0] as usize
+                }
+            },
+        },
+        InstructionSpec {
+            opcode: "jnz",
+            operands: &[OperandType::Label],
+            cycles: 1,
+            execute: |x, pc, ops| {
+                if *x != 0 {
+                    *pc = ops[0] as usize
+                }
+            },
+        },
+    ];
+
+    fn find_spec(opcode: &str) -> Option<&'static InstructionSpec> {
+        INSTRUCTION_SET.iter().find(|spec| spec.opcode == opcode)
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Instruction {
+        pub spec: &'static InstructionSpec,
+        // only the first `spec.operands.len()` are meaningful
+        pub operands: [i32; MAX_OPERANDS],
+    }
+
+    impl Instruction {
+        pub fn operands(&self) -> &[i32] {
+            &self.operands[..self.spec.operands.len()]
+        }
+    }
+
+    impl PartialEq for Instruction {
+        fn eq(&self, other: &Self) -> bool {
+            self.spec.opcode == other.spec.opcode && self.operands() == other.operands()
+        }
+    }
+
+    impl Eq for Instruction {}
+
+    impl Display for Instruction {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.spec.opcode)?;
+            for operand in self.operands() {
+                write!(f, " {operand}")?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Strip the comment (starting with ';' or '#') and the label definitions at the beginning
+    /// of a line, returning the labels and the remaining instruction text
+    fn split_line(line: &str) -> (Vec<&str>, &str) {
+        let mut rest = match line.find([';', '#']) {
+            Some(idx) => &line[..idx],
+            None => line,
+        }
+        .trim();
+        let mut labels = Vec::new();
+        while let Some((label, tail)) = rest.split_once(':') {
+            labels.push(label.trim());
+            rest = tail.trim();
+        }
+        (labels, rest)
+    }
+
+    /// Assemble a program. Each line holds at most one instruction, optionally preceded by
+    /// `label:` definitions and followed by a comment.
+    pub fn assemble(input: &str) -> Result<Vec<Instruction>> {
+        // first pass: locate labels
+        let mut labels = HashMap::new();
+        let mut nb_instr: usize = 0;
+        for (n, line) in input.lines().enumerate() {
+            let (line_labels, instr) = split_line(line);
+            for label in line_labels {
+                if label.is_empty() || label.contains(char::is_whitespace) {
+                    bail!("line {}: invalid label: '{label}'", n + 1);
+                }
+                if labels.insert(label, nb_instr).is_some() {
+                    bail!("line {}: duplicated label: {label}", n + 1);
+                }
+            }
+            if !instr.is_empty() {
+                nb_instr += 1;
+            }
+        }
+
+        // second pass: decode instructions
+        let mut program = Vec::new();
+        for (n, line) in input.lines().enumerate() {
+            let (_, instr) = split_line(line);
+            if instr.is_empty() {
+                continue;
+            }
+            let mut tokens = instr.split_whitespace();
+            let opcode = tokens.next().unwrap();
+            let spec = find_spec(opcode)
+                .with_context(|| format!("line {}: unknown instruction: {opcode}", n + 1))?;
+            let tokens = tokens.collect::<Vec<_>>();
+            if tokens.len() != spec.operands.len() {
+                bail!(
+                    "line {}: {opcode} expects {} operand(s), got {}",
+                    n + 1,
+                    spec.operands.len(),
+                    tokens.len()
+                );
+            }
+            let mut operands = [0; MAX_OPERANDS];
+            for ((operand, token), operand_type) in
+                operands.iter_mut().zip(tokens).zip(spec.operands)
+            {
+                *operand = match operand_type {
+                    OperandType::Int => token
+                        .parse::<i32>()
+                        .with_context(|| format!("line {}: not an integer: {token}", n + 1))?,
+                    OperandType::Label => match labels.get(token) {
+                        Some(&idx) => idx as i32,
+                        None => {
+                            let idx = token.parse::<u32>().with_context(|| {
+                                format!("line {}: unknown label: {token}", n + 1)
+                            })?;
+                            // a jump target can be one past the last instruction
+                            if idx as usize > nb_instr {
+                                bail!("line {}: jump target out of program: {idx}", n + 1);
+                            }
+                            idx as i32
+                        }
+                    },
+                };
+            }
+            program.push(Instruction { spec, operands });
+        }
+        Ok(program)
+    }
+
+    /// Print a program in a form accepted by `assemble`. Jump targets are given `L<index>`
+    /// labels, except the ones out of the program which are kept as numbers.
+    pub fn disassemble(program: &[Instruction]) -> String {
+        let is_target = |idx: usize| {
+            program.iter().any(|instr| {
+                instr
+                    .spec
+                    .operands
+                    .iter()
+                    .zip(instr.operands())
+                    .any(|(t, &op)| *t == OperandType::Label && op as usize == idx)
+            })
+        };
+        let mut out = String::new();
+        // a jump target can be one past the last instruction
+        for idx in 0..=program.len() {
+            if is_target(idx) {
+                out += &format!("L{idx}:\n");
+            }
+            if let Some(instr) = program.get(idx) {
+                out += &format!("    {}", instr.spec.opcode);
+                for (t, op) in instr.spec.operands.iter().zip(instr.operands()) {
+                    match t {
+                        OperandType::Int => out += &format!(" {op}"),
+                        OperandType::Label if *op as usize <= program.len() => {
+                            out += &format!(" L{op}")
+                        }
+                        OperandType::Label => out += &format!(" {op}"),
+                    }
+                }
+                out += "\n";
+            }
+        }
+        out
+    }
+}
+use asm::Instruction;
 
 #[derive(Debug)]
 struct Cpu {
     clock: usize,
     x: i32,
+    program: Vec<Instruction>,
+    // index of the next instruction to load
+    pc: usize,
     // instruction and elapsed cycles on it
     instr: Option<(Instruction, usize)>,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum Instruction {
-    Noop,
-    Addx(i32),
-}
-use Instruction::*;
-
-impl Default for Cpu {
-    fn default() -> Self {
+impl Cpu {
+    fn new(program: Vec<Instruction>) -> Self {
         Self {
             clock: 0,
             x: 1,
+            program,
+            pc: 0,
             instr: None,
         }
     }
-}
 
-impl Cpu {
+    /// Signal strength during the next cycle
     fn get_signal_strength(&self) -> i32 {
-        self.clock as i32 * self.x
+        (self.clock + 1) as i32 * self.x
     }
 
-    fn tick(&mut self) -> Result<()> {
+    fn is_halted(&self) -> bool {
+        self.instr.is_none() && self.pc >= self.program.len()
+    }
+
+    /// Execute one cycle, returning the instruction executed during it and the number of
+    /// cycles elapsed on that instruction
+    fn tick(&mut self) -> Result<(Instruction, usize)> {
+        if self.instr.is_none() {
+            // load new instruction
+            let instr = *self.program.get(self.pc).context("no new instruction")?;
+            self.pc += 1;
+            self.instr = Some((instr, 0));
+        }
+
+        // execute current instruction
         self.clock += 1;
 
-        let mut instr = self.instr.context("cpu has no instruction")?;
-        instr.1 += 1;
+        let (instr, elapsed) = self.instr.context("cpu has no instruction")?;
+        let elapsed = elapsed + 1;
+
+        self.instr = if elapsed >= instr.spec.cycles {
+            (instr.spec.execute)(&mut self.x, &mut self.pc, instr.operands());
+            None
+        } else {
+            Some((instr, elapsed))
+        };
+
+        Ok((instr, elapsed))
+    }
+}
+
+/// Signal strength (as computed for part 1) at each of the given cycles
+fn signal_strengths(program: Vec<Instruction>, cycles: &[usize]) -> Result<Vec<(usize, i32)>> {
+    let last_cycle = cycles.iter().copied().max().unwrap_or(0);
+    let mut cpu = Cpu::new(program);
+    let mut strengths = Vec::new();
+    while cpu.clock < last_cycle && !cpu.is_halted() {
+        // the signal strength is measured during the cycle, before it completes
+        let cycle = cpu.clock + 1;
+        let strength = cpu.get_signal_strength();
+        cpu.tick()?;
+        if cycles.contains(&cycle) {
+            strengths.push((cycle, strength));
+        }
+    }
+    Ok(strengths)
+}
+
+#[derive(Debug)]
+struct TraceEntry {
+    cycle: usize,
+    instr: Instruction,
+    // cycles elapsed on the instruction, including this one
+    elapsed: usize,
+    x_before: i32,
+    x_after: i32,
+    // CRT pixel drawn during the cycle: (row, col, lit)
+    pixel: Option<(usize, usize, bool)>,
+}
+
+/// Execute the program for (at most) the given number of cycles, recording each of them
+fn trace(program: Vec<Instruction>, mut crt: Crt, cycles: usize) -> Result<Vec<TraceEntry>> {
+    let mut cpu = Cpu::new(program);
+    let mut entries = Vec::new();
+    while cpu.clock < cycles && !cpu.is_halted() {
+        let x_before = cpu.x;
+        let pixel = crt.draw(cpu.clock, cpu.x);
+        let (instr, elapsed) = cpu.tick()?;
+        entries.push(TraceEntry {
+            cycle: cpu.clock,
+            instr,
+            elapsed,
+            x_before,
+            x_after: cpu.x,
+            pixel,
+        });
+    }
+    Ok(entries)
+}
+
+fn trace_to_csv(entries: &[TraceEntry]) -> String {
+    let mut out = String::from("cycle,instruction,elapsed,x_before,x_after,crt_row,crt_col,lit\n");
+    for e in entries {
+        let pixel = match e.pixel {
+            Some((i, j, lit)) => format!("{i},{j},{lit}"),
+            None => ",,".to_string(),
+        };
+        out += &format!(
+            "{},{},{},{},{},{pixel}\n",
+            e.cycle, e.instr, e.elapsed, e.x_before, e.x_after
+        );
+    }
+    out
+}
+
+fn trace_to_json(entries: &[TraceEntry]) -> String {
+    let entries = entries
+        .iter()
+        .map(|e| {
+            let pixel = match e.pixel {
+                Some((i, j, lit)) => format!("{{\"row\": {i}, \"col\": {j}, \"lit\": {lit}}}"),
+                None => "null".to_string(),
+            };
+            format!(
+                "  {{\"cycle\": {}, \"instruction\": \"{}\", \"elapsed\": {}, \"x_before\": {}, \"x_after\": {}, \"pixel\": {pixel}}}",
+                e.cycle, e.instr, e.elapsed, e.x_before, e.x_after
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+#[derive(Debug, Clone)]
+struct Crt {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+    pixels: Vec<Vec<bool>>,
+}
+
+impl Default for Crt {
+    fn default() -> Self {
+        Self::new(40, 6, 3)
+    }
+}
+
+impl Crt {
+    fn new(width: usize, height: usize, sprite_width: usize) -> Self {
+        Self {
+            width,
+            height,
+            sprite_width,
+            pixels: vec![vec![false; width]; height],
+        }
+    }
+
+    /// Number of cycles needed to draw a frame
+    fn size(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Whether the sprite centered on `x` covers column `j`
+    fn sprite_covers(&self, x: i32, j: usize) -> bool {
+        let left = x - (self.sprite_width as i32 - 1) / 2;
+        (left..left + self.sprite_width as i32).contains(&(j as i32))
+    }
+
+    /// Draw the pixel at position `idx` of the beam, given the sprite position. Return the
+    /// drawn pixel, if still on screen.
+    fn draw(&mut self, idx: usize, x: i32) -> Option<(usize, usize, bool)> {
+        let i = idx / self.width;
+        let j = idx % self.width;
+        if i < self.height {
+            self.pixels[i][j] = self.sprite_covers(x, j);
+            Some((i, j, self.pixels[i][j]))
+        } else {
+            None
+        }
+    }
 
-        self.instr = match instr {
-            (Noop, 1) => None,
-            (Addx(v), 2) => {
-                self.x += v;
-                None
+    /// Encode the frame as a plain PBM image
+    fn to_pbm(&self) -> String {
+        let mut image = format!("P1\n{} {}\n", self.width, self.height);
+        for row in &self.pixels {
+            let row = row.iter().map(|&p| if p { "1" } else { "0" });
+            image += &row.collect::<Vec<_>>().join(" ");
+            image += "\n";
+        }
+        image
+    }
+
+    fn render_row(&self, i: usize) -> String {
+        self.pixels[i]
+            .iter()
+            .map(|&pixel| if pixel { '#' } else { '.' })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Crt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for i in 0..self.pixels.len() {
+            writeln!(f, "{}", self.render_row(i))?;
+        }
+        Ok(())
+    }
+}
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+// glyphs are separated by a blank column
+const GLYPH_PITCH: usize = GLYPH_WIDTH + 1;
+
+/// Standard AoC 4x6 font
+static FONT: [(char, [&str; GLYPH_HEIGHT]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+/// Read the letters displayed on the CRT
+fn ocr(crt: &Crt) -> Result<String> {
+    let rows = crt.height;
+    let cols = crt.width;
+    if rows != GLYPH_HEIGHT {
+        bail!("CRT height is {rows}, expected {GLYPH_HEIGHT}");
+    }
+    // the blank column after the last glyph is optional
+    if !cols.is_multiple_of(GLYPH_PITCH) && !(cols + 1).is_multiple_of(GLYPH_PITCH) {
+        bail!("CRT width {cols} does not hold a whole number of glyphs");
+    }
+
+    let mut text = String::new();
+    for (n, col) in (0..cols).step_by(GLYPH_PITCH).enumerate() {
+        let glyph = crt
+            .pixels
+            .iter()
+            .map(|row| {
+                row[col..col + GLYPH_WIDTH]
+                    .iter()
+                    .map(|&p| if p { '#' } else { '.' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        let letter = FONT
+            .iter()
+            .find(|(_, pattern)| pattern.iter().zip(&glyph).all(|(p, g)| p == g))
+            .map(|(letter, _)| *letter)
+            .with_context(|| {
+                format!(
+                    "unknown glyph #{n} (columns {col} to {}):\n{}",
+                    col + GLYPH_WIDTH - 1,
+                    glyph.join("\n")
+                )
+            })?;
+        text.push(letter);
+    }
+    Ok(text)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Breakpoint {
+    // stop once the given cycle is completed
+    Cycle(usize),
+    // stop when X takes the given value
+    X(i32),
+}
+
+/// Interactive debugger driving a `Cpu` and the `Crt` it draws on
+struct Debugger {
+    cpu: Cpu,
+    crt: Crt,
+    breakpoints: Vec<Breakpoint>,
+}
+
+const DEBUGGER_HELP: &str = "\
+commands:
+  s, step [N]        execute N cycles (default: 1)
+  c, continue        run until a breakpoint is hit or the program ends
+  r, runto CYCLE     run until CYCLE is completed
+  b, break cycle N   break once cycle N is completed
+  b, break x V       break when X becomes V
+  d, delete N        delete breakpoint number N
+  l, list            list breakpoints
+  i, regs            show the registers
+  crt                show the whole CRT
+  h, help            show this help
+  q, quit            exit the debugger";
+
+impl Debugger {
+    fn new(program: Vec<Instruction>, crt: Crt) -> Self {
+        Self {
+            cpu: Cpu::new(program),
+            crt,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    /// Execute a single cycle, returning the breakpoint hit by it if any
+    fn step(&mut self) -> Result<Option<Breakpoint>> {
+        let prev_x = self.cpu.x;
+        self.crt.draw(self.cpu.clock, self.cpu.x);
+        self.cpu.tick()?;
+        let hit = self.breakpoints.iter().find(|&&b| match b {
+            Breakpoint::Cycle(cycle) => self.cpu.clock == cycle,
+            Breakpoint::X(x) => self.cpu.x == x && prev_x != x,
+        });
+        Ok(hit.copied())
+    }
+
+    /// Run until `stop` returns true, a breakpoint is hit or the program ends
+    fn run_until(&mut self, out: &mut impl Write, stop: impl Fn(&Cpu) -> bool) -> Result<()> {
+        while !self.cpu.is_halted() {
+            let hit = self.step()?;
+            if let Some(b) = hit {
+                writeln!(out, "breakpoint hit: {b:?}")?;
+                break;
+            }
+            if stop(&self.cpu) {
+                break;
+            }
+        }
+        if self.cpu.is_halted() {
+            writeln!(out, "program ended")?;
+        }
+        Ok(())
+    }
+
+    fn print_regs(&self, out: &mut impl Write) -> Result<()> {
+        let instr = match self.cpu.instr {
+            Some((instr, elapsed)) => format!("{instr} (elapsed: {elapsed})"),
+            None => "none".to_string(),
+        };
+        writeln!(
+            out,
+            "cycle: {}  X: {}  pc: {}  instr: {instr}",
+            self.cpu.clock, self.cpu.x, self.cpu.pc
+        )?;
+        Ok(())
+    }
+
+    /// Show the CRT row being drawn, with the sprite and the beam position below it
+    fn print_live_row(&self, out: &mut impl Write) -> Result<()> {
+        let i = self.cpu.clock / self.crt.width;
+        if i >= self.crt.height {
+            return Ok(());
+        }
+        let beam = self.cpu.clock % self.crt.width;
+        let markers: String = (0..self.crt.width)
+            .map(|j| match j {
+                j if j == beam => '^',
+                j if self.crt.sprite_covers(self.cpu.x, j) => '=',
+                _ => ' ',
+            })
+            .collect();
+        writeln!(out, "{}\n{}", self.crt.render_row(i), markers.trim_end())?;
+        Ok(())
+    }
+
+    fn execute(&mut self, command: &[&str], out: &mut impl Write) -> Result<bool> {
+        match command {
+            [] => {}
+            ["s" | "step"] => {
+                self.run_until(out, |_| true)?;
+            }
+            ["s" | "step", n] => {
+                let target = self.cpu.clock + n.parse::<usize>()?;
+                self.run_until(out, |cpu| cpu.clock >= target)?;
+            }
+            ["c" | "continue"] => {
+                self.run_until(out, |_| false)?;
+            }
+            ["r" | "runto", cycle] => {
+                let target = cycle.parse::<usize>()?;
+                self.run_until(out, |cpu| cpu.clock >= target)?;
+            }
+            ["b" | "break", "cycle", n] => {
+                self.breakpoints.push(Breakpoint::Cycle(n.parse()?));
+            }
+            ["b" | "break", "x", v] => {
+                self.breakpoints.push(Breakpoint::X(v.parse()?));
             }
-            _ => Some(instr),
+            ["d" | "delete", n] => {
+                let n = n.parse::<usize>()?;
+                if n >= self.breakpoints.len() {
+                    bail!("no breakpoint number {n}");
+                }
+                self.breakpoints.remove(n);
+            }
+            ["l" | "list"] => {
+                for (n, b) in self.breakpoints.iter().enumerate() {
+                    writeln!(out, "{n}: {b:?}")?;
+                }
+            }
+            ["i" | "regs"] => self.print_regs(out)?,
+            ["crt"] => write!(out, "{}", self.crt)?,
+            ["h" | "help"] => writeln!(out, "{DEBUGGER_HELP}")?,
+            ["q" | "quit"] => return Ok(false),
+            _ => bail!("unknown command, type 'help' for the list of commands"),
         };
+        Ok(true)
+    }
 
+    /// Read commands from `input` until it is exhausted or the user quits
+    fn repl(&mut self, input: impl BufRead, mut out: impl Write) -> Result<()> {
+        write!(out, "(cpu) ")?;
+        out.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let command = line.split_whitespace().collect::<Vec<_>>();
+            match self.execute(&command, &mut out) {
+                Ok(true) => {
+                    self.print_regs(&mut out)?;
+                    self.print_live_row(&mut out)?;
+                }
+                Ok(false) => return Ok(()),
+                Err(e) => writeln!(out, "error: {e}")?,
+            }
+            write!(out, "(cpu) ")?;
+            out.flush()?;
+        }
         Ok(())
     }
 }
@@ -53,94 +682,195 @@ static INSPECTION: [usize; 6] = [20, 60, 100, 140, 180, 220];
 
 const INPUT: &str = include_str!("../data/10.txt");
 
-fn main() -> Result<()> {
-    // part 1
-    let mut lines = INPUT.lines();
-    let mut cpu = Cpu::default();
-    let mut sum = 0;
-    loop {
-        if cpu.clock > 220 {
-            break;
+/// Value following the `name` option in the command line arguments
+fn option_value<'a>(args: &'a [String], name: &str) -> Result<Option<&'a str>> {
+    match args.iter().position(|a| a == name) {
+        Some(i) => {
+            let value = args
+                .get(i + 1)
+                .with_context(|| format!("{name} requires a value"))?;
+            Ok(Some(value))
         }
+        None => Ok(None),
+    }
+}
 
-        if cpu.instr.is_none() {
-            // load new instruction
-            let line = lines.next().context("no new instruction")?;
-
-            let instr = if line.starts_with("noop") {
-                Noop
-            } else if line.starts_with("addx") {
-                let (_, v) = line
-                    .split_once(' ')
-                    .with_context(|| format!("invalid instruction: {}", line))?;
-                let v = v.parse::<i32>()?;
-                Addx(v)
-            } else {
-                bail!("unknown instruction: {}", line);
-            };
-
-            cpu.instr = Some((instr, 0));
+fn main() -> Result<()> {
+    // usage: 10 [--program FILE] [--disasm] [--debug] [--signal CYCLE,...]
+    //           [--trace csv|json [--cycles N]]
+    //           [--width W] [--height H] [--sprite S] [--image FILE] [--frames DIR]
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let program = match option_value(&args, "--program")? {
+        Some(file) => asm::assemble(&std::fs::read_to_string(file)?)?,
+        None => asm::assemble(INPUT)?,
+    };
+    let default_crt = Crt::default();
+    let parse_or = |name, default| -> Result<usize> {
+        match option_value(&args, name)? {
+            Some(v) => Ok(v.parse::<usize>()?),
+            None => Ok(default),
         }
+    };
+    let crt = Crt::new(
+        parse_or("--width", default_crt.width)?,
+        parse_or("--height", default_crt.height)?,
+        parse_or("--sprite", default_crt.sprite_width)?,
+    );
+    if crt.width == 0 {
+        bail!("CRT width must be positive");
+    }
 
-        // execute current instruction
-        cpu.tick()?;
+    if args.iter().any(|a| a == "--disasm") {
+        print!("{}", asm::disassemble(&program));
+        return Ok(());
+    }
+    if args.iter().any(|a| a == "--debug") {
+        let mut debugger = Debugger::new(program, crt);
+        return debugger.repl(std::io::stdin().lock(), std::io::stdout());
+    }
 
-        // accumulate signal strength
-        if INSPECTION.contains(&cpu.clock) {
-            sum += cpu.get_signal_strength();
+    if let Some(cycles) = option_value(&args, "--signal")? {
+        let cycles = cycles
+            .split(',')
+            .map(|c| c.parse::<usize>())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        for (cycle, strength) in signal_strengths(program, &cycles)? {
+            println!("{cycle} {strength}");
         }
+        return Ok(());
     }
+    if let Some(format) = option_value(&args, "--trace")? {
+        let cycles = parse_or("--cycles", crt.size())?;
+        let entries = trace(program, crt, cycles)?;
+        match format {
+            "csv" => print!("{}", trace_to_csv(&entries)),
+            "json" => print!("{}", trace_to_json(&entries)),
+            _ => bail!("unknown trace format: {format}, expected csv or json"),
+        }
+        return Ok(());
+    }
+
+    // part 1
+    let sum: i32 = signal_strengths(program.clone(), &INSPECTION)?
+        .iter()
+        .map(|(_, strength)| strength)
+        .sum();
     println!("{sum}");
 
     // part 2
-    let mut lines = INPUT.lines();
-    let mut crt: [[bool; 40]; 6] = [[false; 40]; 6];
-    let mut idx: usize = 0;
-    let mut cpu = Cpu::default();
-    loop {
-        if cpu.clock >= 240 {
-            break;
-        }
-
-        // update CRT
-        let i = idx / 40;
-        let j = idx % 40;
-        crt[i][j] = (j as i32 - cpu.x).abs() <= 1;
-        idx += 1;
-
-        if cpu.instr.is_none() {
-            // load new instruction
-            let line = lines.next().context("no new instruction")?;
-
-            let instr = if line.starts_with("noop") {
-                Noop
-            } else if line.starts_with("addx") {
-                let (_, v) = line
-                    .split_once(' ')
-                    .with_context(|| format!("invalid instruction: {}", line))?;
-                let v = v.parse::<i32>()?;
-                Addx(v)
-            } else {
-                bail!("unknown instruction: {}", line);
-            };
-
-            cpu.instr = Some((instr, 0));
+    let mut crt = crt;
+    let frames_dir = option_value(&args, "--frames")?.map(std::path::Path::new);
+    let mut cpu = Cpu::new(program);
+    // the screen may be larger than what the program draws
+    while cpu.clock < crt.size() && !cpu.is_halted() {
+        crt.draw(cpu.clock, cpu.x);
+        cpu.tick()?;
+        if let Some(dir) = frames_dir {
+            let frame = dir.join(format!("frame_{:05}.pbm", cpu.clock));
+            std::fs::write(frame, crt.to_pbm())?;
+        }
+    }
+    if let Some(file) = option_value(&args, "--image")? {
+        std::fs::write(file, crt.to_pbm())?;
+    }
+    if (crt.width, crt.height, crt.sprite_width)
+        != (
+            default_crt.width,
+            default_crt.height,
+            default_crt.sprite_width,
+        )
+    {
+        // only the standard screen and sprite display letters
+        print!("{crt}");
+        return Ok(());
+    }
+    match ocr(&crt) {
+        Ok(text) => println!("{text}"),
+        Err(e) => {
+            print!("{crt}");
+            return Err(e);
         }
+    }
 
-        // execute current instruction
-        cpu.tick()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembler_round_trip() {
+        let source = "\
+            start: addx 3 ; comment
+            # full line comment
+            loop:
+                subx 1
+                jnz loop
+            jz end
+            noop
+            jmp start
+            end:";
+        let program = asm::assemble(source).unwrap();
+        assert_eq!(program.len(), 6);
+        assert_eq!(program[2].operands(), &[1]);
+        assert_eq!(program[3].operands(), &[6]);
+
+        let disassembled = asm::disassemble(&program);
+        assert_eq!(asm::assemble(&disassembled).unwrap(), program);
+
+        assert!(asm::assemble("jmp 7").is_err());
+        assert!(asm::assemble("jmp 1").is_ok());
+
+        let program = asm::assemble(INPUT).unwrap();
+        assert_eq!(asm::assemble(&asm::disassemble(&program)).unwrap(), program);
+    }
+
+    #[test]
+    fn signal_strength_during_cycle() {
+        let program = asm::assemble("noop\naddx 3\naddx -5").unwrap();
+        assert_eq!(
+            signal_strengths(program, &[3, 5]).unwrap(),
+            [(3, 3), (5, 20)]
+        );
+
+        let program = asm::assemble(include_str!("../data/10_example.txt")).unwrap();
+        let strengths = signal_strengths(program, &INSPECTION).unwrap();
+        assert_eq!(
+            strengths,
+            [
+                (20, 420),
+                (60, 1140),
+                (100, 1800),
+                (140, 2940),
+                (180, 2880),
+                (220, 3960)
+            ]
+        );
+        assert_eq!(strengths.iter().map(|(_, s)| s).sum::<i32>(), 13140);
     }
 
-    for row in crt.iter() {
-        for pixel in row.iter() {
-            if *pixel {
-                print!("#");
-            } else {
-                print!(".");
+    #[test]
+    fn crt_ocr() {
+        let mut crt = Crt::default();
+        for (n, (_, pattern)) in FONT.iter().take(8).enumerate() {
+            for (i, line) in pattern.iter().enumerate() {
+                for (j, c) in line.chars().enumerate() {
+                    crt.pixels[i][n * GLYPH_PITCH + j] = c == '#';
+                }
             }
         }
-        println!();
-    }
+        assert_eq!(ocr(&crt).unwrap(), "ABCEFGHI");
 
-    Ok(())
+        crt.pixels[0][0] = true;
+        assert!(ocr(&crt).is_err());
+
+        let mut crt = Crt::default();
+        let mut cpu = Cpu::new(asm::assemble(INPUT).unwrap());
+        while cpu.clock < crt.size() {
+            crt.draw(cpu.clock, cpu.x);
+            cpu.tick().unwrap();
+        }
+        assert_eq!(ocr(&crt).unwrap(), "ZCBAJFJZ");
+    }
 }
@@ -1,226 +1,1045 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use itertools::{izip, Itertools};
+use num_bigint::BigUint;
+use num_traits::Zero;
 use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::Write;
 use std::ops::{Add, Mul};
+use std::rc::Rc;
 use std::vec::Vec;
 
+/// Arithmetic needed on worry levels to play keep away
+trait Worry: Clone {
+    /// Whether `div_const` is supported, i.e. the value is exact
+    const SUPPORTS_DIVISION: bool;
+
+    fn add(&self, rhs: &Self) -> Self;
+    fn mul(&self, rhs: &Self) -> Self;
+    fn add_const(&self, c: u64) -> Self;
+    fn mul_const(&self, c: u64) -> Self;
+    fn div_const(&self, c: u64) -> Self;
+    fn is_divisible_by(&self, divisor: u64) -> bool;
+
+    /// Whether the value is no longer exact because an operation overflowed
+    fn overflowed(&self) -> bool {
+        false
+    }
+
+    fn square(&self) -> Self {
+        self.mul(self)
+    }
+}
+
+/// Native integer, `None` once an operation overflowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Checked(Option<u64>);
+
+impl Checked {
+    fn map(&self, f: impl Fn(u64) -> Option<u64>) -> Self {
+        Self(self.0.and_then(f))
+    }
+}
+
+impl Worry for Checked {
+    const SUPPORTS_DIVISION: bool = true;
+
+    fn add(&self, rhs: &Self) -> Self {
+        self.map(|v| rhs.0?.checked_add(v))
+    }
+
+    fn mul(&self, rhs: &Self) -> Self {
+        self.map(|v| rhs.0?.checked_mul(v))
+    }
+
+    fn add_const(&self, c: u64) -> Self {
+        self.map(|v| v.checked_add(c))
+    }
+
+    fn mul_const(&self, c: u64) -> Self {
+        self.map(|v| v.checked_mul(c))
+    }
+
+    fn div_const(&self, c: u64) -> Self {
+        self.map(|v| Some(v / c))
+    }
+
+    fn is_divisible_by(&self, divisor: u64) -> bool {
+        self.0.is_some_and(|v| v.is_multiple_of(divisor))
+    }
+
+    fn overflowed(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+impl Display for Checked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(v) => write!(f, "{v}"),
+            None => write!(f, "overflow"),
+        }
+    }
+}
+
+impl Worry for BigUint {
+    const SUPPORTS_DIVISION: bool = true;
+
+    fn add(&self, rhs: &Self) -> Self {
+        self + rhs
+    }
+
+    fn mul(&self, rhs: &Self) -> Self {
+        self * rhs
+    }
+
+    fn add_const(&self, c: u64) -> Self {
+        self + c
+    }
+
+    fn mul_const(&self, c: u64) -> Self {
+        self * c
+    }
+
+    fn div_const(&self, c: u64) -> Self {
+        self / c
+    }
+
+    fn is_divisible_by(&self, divisor: u64) -> bool {
+        (self % divisor).is_zero()
+    }
+}
+
+fn mul_mod(a: u64, b: u64, modulus: u64) -> u64 {
+    ((a as u128 * b as u128) % modulus as u128) as u64
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: u64, b: u64) -> Option<u64> {
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+/// Lcm of all the divisors, the modulus of the lcm backend
+fn divisors_lcm(divisors: &[u64]) -> Result<u64> {
+    divisors
+        .iter()
+        .try_fold(1, |acc, &d| lcm(acc, d))
+        .context("lcm of the divisors overflows")
+}
+
+/// Residue number system: a value is stored as its remainders for a set of moduli known at
+/// runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rns {
+    moduli: Rc<[u64]>,
+    residues: Vec<u64>,
+}
+
+impl Rns {
+    fn new(v: u64, moduli: &Rc<[u64]>) -> Self {
+        Self {
+            moduli: moduli.clone(),
+            residues: moduli.iter().map(|p| v % p).collect(),
+        }
+    }
+
+    fn map(&self, f: impl Fn(u64, u64) -> u64) -> Self {
+        Self {
+            moduli: self.moduli.clone(),
+            residues: self
+                .residues
+                .iter()
+                .zip(self.moduli.iter())
+                .map(|(&r, &p)| f(r, p))
+                .collect(),
+        }
+    }
+
+    fn zip_map(&self, rhs: &Self, f: impl Fn(u64, u64, u64) -> u64) -> Self {
+        assert!(Rc::ptr_eq(&self.moduli, &rhs.moduli) || self.moduli == rhs.moduli);
+        Self {
+            moduli: self.moduli.clone(),
+            residues: izip!(&self.residues, &rhs.residues, self.moduli.iter())
+                .map(|(&r1, &r2, &p)| f(r1, r2, p))
+                .collect(),
+        }
+    }
+}
+
+impl Add for &Rns {
+    type Output = Rns;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.zip_map(rhs, |r1, r2, p| (r1 + r2) % p)
+    }
+}
+
+impl Mul for &Rns {
+    type Output = Rns;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.zip_map(rhs, mul_mod)
+    }
+}
+
+impl Worry for Rns {
+    const SUPPORTS_DIVISION: bool = false;
+
+    fn add(&self, rhs: &Self) -> Self {
+        self + rhs
+    }
+
+    fn mul(&self, rhs: &Self) -> Self {
+        self * rhs
+    }
+
+    fn add_const(&self, c: u64) -> Self {
+        self.map(|r, p| (r + c % p) % p)
+    }
+
+    fn mul_const(&self, c: u64) -> Self {
+        self.map(|r, p| mul_mod(r, c % p, p))
+    }
+
+    fn div_const(&self, _c: u64) -> Self {
+        panic!("division is not supported in a residue number system")
+    }
+
+    fn is_divisible_by(&self, divisor: u64) -> bool {
+        // any modulus multiple of the divisor gives the answer
+        let idx = self
+            .moduli
+            .iter()
+            .position(|p| p.is_multiple_of(divisor))
+            .expect("divisor not tracked by the residue number system");
+        self.residues[idx].is_multiple_of(divisor)
+    }
+
+    fn square(&self) -> Self {
+        self.map(|r, p| mul_mod(r, r, p))
+    }
+}
+
+impl Display for Rns {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}]", self.residues.iter().join(", "))
+    }
+}
+
+/// Value stored modulo the lcm of all the divisors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ModLcm {
+    value: u64,
+    modulus: u64,
+}
+
+impl ModLcm {
+    fn new(v: u64, modulus: u64) -> Self {
+        Self {
+            value: v % modulus,
+            modulus,
+        }
+    }
+}
+
+impl Worry for ModLcm {
+    const SUPPORTS_DIVISION: bool = false;
+
+    fn add(&self, rhs: &Self) -> Self {
+        Self::new(self.value + rhs.value, self.modulus)
+    }
+
+    fn mul(&self, rhs: &Self) -> Self {
+        Self::new(mul_mod(self.value, rhs.value, self.modulus), self.modulus)
+    }
+
+    fn add_const(&self, c: u64) -> Self {
+        Self::new(self.value + c % self.modulus, self.modulus)
+    }
+
+    fn mul_const(&self, c: u64) -> Self {
+        Self::new(mul_mod(self.value, c, self.modulus), self.modulus)
+    }
+
+    fn div_const(&self, _c: u64) -> Self {
+        panic!("division is not supported modulo lcm")
+    }
+
+    fn is_divisible_by(&self, divisor: u64) -> bool {
+        assert!(self.modulus.is_multiple_of(divisor));
+        self.value.is_multiple_of(divisor)
+    }
+}
+
+impl Display for ModLcm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (mod {})", self.value, self.modulus)
+    }
+}
+
+/// What happens to the worry level after a monkey inspected an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Relief {
+    None,
+    Divide(u64),
+}
+
+/// Representation of worry levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    // native integers, the game stops with an error if they overflow
+    Exact,
+    BigInt,
+    Rns,
+    Lcm,
+}
+
+impl TryFrom<&str> for Backend {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        match value {
+            "exact" => Ok(Backend::Exact),
+            "bigint" => Ok(Backend::BigInt),
+            "rns" => Ok(Backend::Rns),
+            "lcm" => Ok(Backend::Lcm),
+            s => Err(anyhow!("unknown backend: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add,
+    Mul,
+}
+
+/// Operation of a monkey, computing the new worry level from the old one
+#[derive(Debug, Clone)]
+enum Expr {
+    Old,
+    Const(u64),
+    BinOp(Op, Box<Expr>, Box<Expr>),
+}
+
+// intermediate value when evaluating an expression, constants are kept apart so that
+// worry level types do not need to build them
+enum Value<T> {
+    Const(u64),
+    Worry(T),
+}
+
+impl Expr {
+    /// Parse space-separated tokens made of `old`, integers, `+` and `*`, with the usual
+    /// precedence
+    fn parse(s: &str) -> Result<Self> {
+        let parse_operand = |token: &str| match token {
+            "old" => Ok(Expr::Old),
+            c => c
+                .parse::<u64>()
+                .map(Expr::Const)
+                .with_context(|| format!("invalid operand: {c}")),
+        };
+        let fold = |op, operands: Vec<Expr>| {
+            operands
+                .into_iter()
+                .reduce(|lhs, rhs| Expr::BinOp(op, Box::new(lhs), Box::new(rhs)))
+                .context("missing operand")
+        };
+
+        let mut terms = Vec::new();
+        for term in s.split('+') {
+            let factors = term
+                .split('*')
+                .map(|factor| parse_operand(factor.trim()))
+                .collect::<Result<Vec<_>>>()?;
+            terms.push(fold(Op::Mul, factors)?);
+        }
+        fold(Op::Add, terms)
+    }
+
+    fn eval<T: Worry>(&self, old: &T) -> T {
+        match self.eval_value(old) {
+            Value::Worry(v) => v,
+            Value::Const(_) => panic!("operation does not depend on old value"),
+        }
+    }
+
+    fn eval_value<T: Worry>(&self, old: &T) -> Value<T> {
+        match self {
+            Expr::Old => Value::Worry(old.clone()),
+            Expr::Const(c) => Value::Const(*c),
+            Expr::BinOp(op, lhs, rhs) => match (op, lhs.eval_value(old), rhs.eval_value(old)) {
+                (Op::Add, Value::Const(a), Value::Const(b)) => Value::Const(a + b),
+                (Op::Mul, Value::Const(a), Value::Const(b)) => Value::Const(a * b),
+                (Op::Add, Value::Worry(a), Value::Const(c))
+                | (Op::Add, Value::Const(c), Value::Worry(a)) => Value::Worry(a.add_const(c)),
+                (Op::Mul, Value::Worry(a), Value::Const(c))
+                | (Op::Mul, Value::Const(c), Value::Worry(a)) => Value::Worry(a.mul_const(c)),
+                (Op::Add, Value::Worry(a), Value::Worry(b)) => Value::Worry(a.add(&b)),
+                (Op::Mul, Value::Worry(a), Value::Worry(b)) => {
+                    if matches!((&**lhs, &**rhs), (Expr::Old, Expr::Old)) {
+                        Value::Worry(a.square())
+                    } else {
+                        Value::Worry(a.mul(&b))
+                    }
+                }
+            },
+        }
+    }
+
+    /// How the operation changes the worry level, in the puzzle's words
+    fn describe(&self) -> String {
+        match self {
+            Expr::BinOp(Op::Mul, lhs, rhs) => match (&**lhs, &**rhs) {
+                (Expr::Old, Expr::Old) => "is multiplied by itself".to_string(),
+                (Expr::Old, Expr::Const(c)) | (Expr::Const(c), Expr::Old) => {
+                    format!("is multiplied by {c}")
+                }
+                _ => format!("becomes {self}"),
+            },
+            Expr::BinOp(Op::Add, lhs, rhs) => match (&**lhs, &**rhs) {
+                (Expr::Old, Expr::Const(c)) | (Expr::Const(c), Expr::Old) => {
+                    format!("increases by {c}")
+                }
+                _ => format!("becomes {self}"),
+            },
+            _ => format!("becomes {self}"),
+        }
+    }
+
+    fn uses_old(&self) -> bool {
+        match self {
+            Expr::Old => true,
+            Expr::Const(_) => false,
+            Expr::BinOp(_, lhs, rhs) => lhs.uses_old() || rhs.uses_old(),
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Old => write!(f, "old"),
+            Expr::Const(c) => write!(f, "{c}"),
+            Expr::BinOp(op, lhs, rhs) => {
+                let op = match op {
+                    Op::Add => "+",
+                    Op::Mul => "*",
+                };
+                // products are evaluated first, sums within them need parentheses
+                let fmt_operand = |e: &Expr| match (op, e) {
+                    ("*", Expr::BinOp(Op::Add, _, _)) => format!("({e})"),
+                    _ => format!("{e}"),
+                };
+                write!(f, "{} {op} {}", fmt_operand(lhs), fmt_operand(rhs))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 struct Monkey<T> {
     inspected: usize,
     items: VecDeque<T>,
-    operation: Box<dyn Fn(&T) -> T>,
-    test: Box<dyn Fn(&T) -> usize>,
+    operation: Expr,
+    divisor: u64,
+    if_true: usize,
+    if_false: usize,
+}
+
+impl<T> Monkey<T> {
+    fn map_items<U>(self, f: impl Fn(T) -> U) -> Monkey<U> {
+        Monkey {
+            inspected: self.inspected,
+            items: self.items.into_iter().map(f).collect(),
+            operation: self.operation,
+            divisor: self.divisor,
+            if_true: self.if_true,
+            if_false: self.if_false,
+        }
+    }
 }
 
-const MONKEY_NB: usize = 8;
-const MONKEY_DIVS: [i32; MONKEY_NB] = [19, 3, 13, 17, 2, 11, 5, 7];
+fn parse_monkey(id: usize, input: &str) -> Result<Monkey<u64>> {
+    let mut lines = input.lines().map(|line| line.trim());
+    let mut next_field = |prefix: &str| {
+        let line = lines
+            .next()
+            .with_context(|| format!("missing line: {prefix}"))?;
+        line.strip_prefix(prefix)
+            .map(|value| value.trim())
+            .with_context(|| format!("expected '{prefix}', got '{line}'"))
+    };
 
-// store remainder for each monkey
-#[derive(Debug, Clone, Copy)]
-struct ItemRem([i32; MONKEY_NB]);
+    let header = next_field("Monkey")?;
+    let header_id = header
+        .strip_suffix(':')
+        .with_context(|| format!("invalid header: {header}"))?
+        .parse::<usize>()?;
+    if header_id != id {
+        bail!("expected monkey {id}, got monkey {header_id}");
+    }
+
+    let items = next_field("Starting items:")?;
+    let items = items
+        .split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            item.parse::<u64>()
+                .with_context(|| format!("invalid item: {item}"))
+        })
+        .collect::<Result<VecDeque<_>>>()?;
+
+    let operation = Expr::parse(next_field("Operation: new =")?)?;
+    if !operation.uses_old() {
+        bail!("operation does not depend on old value");
+    }
+    let divisor = next_field("Test: divisible by")?.parse::<u64>()?;
+    if divisor == 0 {
+        bail!("cannot test divisibility by 0");
+    }
+    let if_true = next_field("If true: throw to monkey")?.parse::<usize>()?;
+    let if_false = next_field("If false: throw to monkey")?.parse::<usize>()?;
 
-impl ItemRem {
-    fn new(v: i32) -> Self {
-        let mut rems = [0; MONKEY_NB];
-        for (r, p) in rems.iter_mut().zip(MONKEY_DIVS) {
-            *r = v % p;
+    Ok(Monkey {
+        inspected: 0,
+        items,
+        operation,
+        divisor,
+        if_true,
+        if_false,
+    })
+}
+
+fn parse_monkeys(input: &str) -> Result<Vec<Monkey<u64>>> {
+    let monkeys = input
+        .split("\n\n")
+        .filter(|block| !block.trim().is_empty())
+        .enumerate()
+        .map(|(id, block)| parse_monkey(id, block).with_context(|| format!("monkey {id}")))
+        .collect::<Result<Vec<_>>>()?;
+    for (id, m) in monkeys.iter().enumerate() {
+        for target in [m.if_true, m.if_false] {
+            if target >= monkeys.len() {
+                bail!("monkey {id} throws to unknown monkey {target}");
+            }
+            if target == id {
+                bail!("monkey {id} throws to itself");
+            }
         }
-        Self(rems)
     }
+    Ok(monkeys)
 }
 
-impl Add<i32> for ItemRem {
-    type Output = Self;
+/// What happened when a monkey inspected an item
+struct Inspection<'a, T> {
+    monkey: usize,
+    old: &'a T,
+    new: &'a T,
+    // worry level after relief, equal to `new` without relief
+    relieved: &'a T,
+    divisible: bool,
+    target: usize,
+}
+
+/// Play a single round, calling `observe` on every inspection
+fn play_round<T: Worry>(
+    monkeys: &mut [Monkey<T>],
+    relief: Relief,
+    mut observe: impl FnMut(Inspection<T>),
+) -> Result<()> {
+    for m in 0..monkeys.len() {
+        while let Some(item) = monkeys[m].items.pop_front() {
+            let new = monkeys[m].operation.eval(&item);
+            let relieved = match relief {
+                Relief::None => new.clone(),
+                Relief::Divide(d) => new.div_const(d),
+            };
+            if new.overflowed() {
+                bail!("worry level overflow, try another backend");
+            }
+            let divisible = relieved.is_divisible_by(monkeys[m].divisor);
+            let target = if divisible {
+                monkeys[m].if_true
+            } else {
+                monkeys[m].if_false
+            };
+            observe(Inspection {
+                monkey: m,
+                old: &item,
+                new: &new,
+                relieved: &relieved,
+                divisible,
+                target,
+            });
+            monkeys[m].inspected += 1;
+            monkeys[target].items.push_back(relieved);
+        }
+    }
+    Ok(())
+}
+
+fn play_rounds<T: Worry>(monkeys: &mut [Monkey<T>], rounds: usize, relief: Relief) -> Result<()> {
+    if relief != Relief::None && !T::SUPPORTS_DIVISION {
+        bail!("relief requires exact worry levels");
+    }
+    for _ in 0..rounds {
+        play_round(monkeys, relief, |_| {})?;
+    }
+    Ok(())
+}
+
+/// Record of a round
+struct RoundStats {
+    // for each item, the monkeys holding it during the round, in order
+    paths: Vec<Vec<usize>>,
+    // number of items inspected by each monkey since the beginning
+    inspected: Vec<usize>,
+}
+
+/// Narrate an inspection as in the puzzle
+fn narrate_inspection<T: Display>(
+    inspection: &Inspection<T>,
+    operation: &Expr,
+    divisor: u64,
+    relief: Relief,
+) -> String {
+    let mut text = format!(
+        "  Monkey inspects an item with a worry level of {}.\n",
+        inspection.old
+    );
+    text += &format!(
+        "    Worry level {} to {}.\n",
+        operation.describe(),
+        inspection.new
+    );
+    if let Relief::Divide(d) = relief {
+        text += &format!(
+            "    Monkey gets bored with item. Worry level is divided by {d} to {}.\n",
+            inspection.relieved
+        );
+    }
+    let not = if inspection.divisible { "" } else { "not " };
+    text += &format!("    Current worry level is {not}divisible by {divisor}.\n");
+    text += &format!(
+        "    Item with worry level {} is thrown to monkey {}.\n",
+        inspection.relieved, inspection.target
+    );
+    text
+}
+
+/// Play rounds while recording the path of every item. The first `narrate` rounds are
+/// narrated to `out` as in the puzzle.
+fn play_traced<T: Worry + Display>(
+    monkeys: &mut [Monkey<T>],
+    rounds: usize,
+    relief: Relief,
+    narrate: usize,
+    out: &mut impl Write,
+) -> Result<Vec<RoundStats>> {
+    if relief != Relief::None && !T::SUPPORTS_DIVISION {
+        bail!("relief requires exact worry levels");
+    }
+
+    // items are numbered in their initial order, the ids move along with the items
+    let mut holders: Vec<VecDeque<usize>> = Vec::new();
+    let mut nb_items = 0;
+    for m in monkeys.iter() {
+        holders.push((nb_items..nb_items + m.items.len()).collect());
+        nb_items += m.items.len();
+    }
+    let rules = monkeys
+        .iter()
+        .map(|m| (m.operation.clone(), m.divisor))
+        .collect_vec();
+
+    let mut stats = Vec::new();
+    for round in 1..=rounds {
+        let mut paths = vec![Vec::new(); nb_items];
+        for (m, ids) in holders.iter().enumerate() {
+            for &id in ids {
+                paths[id].push(m);
+            }
+        }
+
+        let narrated = round <= narrate;
+        let mut text = String::new();
+        let mut next_turn = 0;
+        play_round(monkeys, relief, |inspection| {
+            let id = holders[inspection.monkey].pop_front().unwrap();
+            holders[inspection.target].push_back(id);
+            paths[id].push(inspection.target);
+            if narrated {
+                while next_turn <= inspection.monkey {
+                    text += &format!("Monkey {next_turn}:\n");
+                    next_turn += 1;
+                }
+                let (operation, divisor) = &rules[inspection.monkey];
+                text += &narrate_inspection(&inspection, operation, *divisor, relief);
+            }
+        })?;
+
+        stats.push(RoundStats {
+            paths,
+            inspected: monkeys.iter().map(|m| m.inspected).collect(),
+        });
+
+        if narrated {
+            for turn in next_turn..monkeys.len() {
+                text += &format!("Monkey {turn}:\n");
+            }
+            writeln!(out, "{text}")?;
+            writeln!(
+                out,
+                "After round {round}, the monkeys are holding items with these worry levels:"
+            )?;
+            for (m, monkey) in monkeys.iter().enumerate() {
+                writeln!(out, "Monkey {m}: {}", monkey.items.iter().join(", "))?;
+            }
+            writeln!(out, "\nItem paths during round {round}:")?;
+            for (id, path) in stats[round - 1].paths.iter().enumerate() {
+                writeln!(out, "Item {id}: {}", path.iter().join(" -> "))?;
+            }
+            writeln!(out)?;
+        }
+    }
+    Ok(stats)
+}
 
-    fn add(self, rhs: i32) -> Self::Output {
-        let mut rems = self.0;
-        for (r, p) in rems.iter_mut().zip(MONKEY_DIVS) {
-            *r += rhs;
-            *r %= p;
+/// Table of the number of items inspected by each monkey after the given rounds
+fn summary_table(stats: &[RoundStats], rounds: &[usize]) -> String {
+    let nb_monkeys = stats.first().map_or(0, |s| s.inspected.len());
+    let mut table = format!("{:>8}", "round");
+    for m in 0..nb_monkeys {
+        table += &format!(" {:>10}", format!("monkey {m}"));
+    }
+    table += &format!(" {:>16}\n", "monkey business");
+    for &round in rounds {
+        let Some(s) = round.checked_sub(1).and_then(|r| stats.get(r)) else {
+            continue;
+        };
+        table += &format!("{round:>8}");
+        for inspected in &s.inspected {
+            table += &format!(" {inspected:>10}");
         }
-        Self(rems)
+        table += &format!(" {:>16}\n", monkey_business(&s.inspected));
     }
+    table
 }
 
-impl Mul<i32> for ItemRem {
-    type Output = Self;
+/// Optional reporting on the game
+struct Report {
+    // number of rounds to narrate
+    narrate: usize,
+    // rounds after which inspection counts are summarized
+    summary: Vec<usize>,
+}
 
-    fn mul(self, rhs: i32) -> Self::Output {
-        let mut rems = self.0;
-        for (r, p) in rems.iter_mut().zip(MONKEY_DIVS) {
-            *r *= rhs;
-            *r %= p;
+/// Number of items inspected by each monkey, using the given worry level representation
+fn inspections(
+    monkeys: &[Monkey<u64>],
+    backend: Backend,
+    rounds: usize,
+    relief: Relief,
+    report: Option<&Report>,
+) -> Result<Vec<usize>> {
+    fn play<T: Worry + Display>(
+        monkeys: &[Monkey<u64>],
+        f: impl Fn(u64) -> T,
+        rounds: usize,
+        relief: Relief,
+        report: Option<&Report>,
+    ) -> Result<Vec<usize>> {
+        let mut monkeys = monkeys
+            .iter()
+            .map(|m| m.clone().map_items(&f))
+            .collect_vec();
+        match report {
+            Some(report) => {
+                let mut out = std::io::stdout().lock();
+                let stats = play_traced(&mut monkeys, rounds, relief, report.narrate, &mut out)?;
+                if !report.summary.is_empty() {
+                    write!(out, "{}", summary_table(&stats, &report.summary))?;
+                }
+            }
+            None => play_rounds(&mut monkeys, rounds, relief)?,
         }
-        Self(rems)
+        Ok(monkeys.iter().map(|m| m.inspected).collect())
+    }
+
+    let divisors = monkeys.iter().map(|m| m.divisor).collect::<Rc<[u64]>>();
+    match backend {
+        Backend::Exact => play(monkeys, |v| Checked(Some(v)), rounds, relief, report),
+        Backend::BigInt => play(monkeys, BigUint::from, rounds, relief, report),
+        Backend::Rns => play(monkeys, |v| Rns::new(v, &divisors), rounds, relief, report),
+        Backend::Lcm => {
+            let modulus = divisors_lcm(&divisors)?;
+            play(monkeys, |v| ModLcm::new(v, modulus), rounds, relief, report)
+        }
+    }
+}
+
+/// Follow an item held by `holder` for a whole round, adding the inspections made on it to
+/// `inspected`. Return its holder and worry level at the beginning of the next round.
+fn item_round<T: Worry>(
+    monkeys: &[Monkey<T>],
+    (holder, worry_level): (usize, T),
+    inspected: &mut [usize],
+) -> (usize, T) {
+    let mut m = holder;
+    let mut worry_level = worry_level;
+    loop {
+        worry_level = monkeys[m].operation.eval(&worry_level);
+        inspected[m] += 1;
+        let target = if worry_level.is_divisible_by(monkeys[m].divisor) {
+            monkeys[m].if_true
+        } else {
+            monkeys[m].if_false
+        };
+        if target < m {
+            // the target monkey already played this round
+            return (target, worry_level);
+        }
+        m = target;
     }
 }
 
-impl Mul<Self> for ItemRem {
-    type Output = Self;
+/// Find the number of rounds before an item enters its cycle and the length of the cycle,
+/// with Brent's algorithm. Give up after `limit` rounds.
+fn detect_cycle<T: Worry + PartialEq>(
+    monkeys: &[Monkey<T>],
+    start: (usize, T),
+    limit: u64,
+) -> Option<(u64, u64)> {
+    let mut ignored = vec![0; monkeys.len()];
+    let mut next = |state| item_round(monkeys, state, &mut ignored);
 
-    fn mul(self, rhs: Self) -> Self::Output {
-        let mut rems = self.0;
-        for (r1, r2, p) in izip!(rems.iter_mut(), rhs.0, MONKEY_DIVS) {
-            *r1 *= r2;
-            *r1 %= p;
+    // find the cycle length
+    let mut power = 1;
+    let mut lambda = 1;
+    let mut tortoise = start.clone();
+    let mut hare = next(start.clone());
+    let mut steps = 1;
+    while tortoise != hare {
+        if power == lambda {
+            tortoise = hare.clone();
+            power *= 2;
+            lambda = 0;
         }
-        Self(rems)
+        hare = next(hare);
+        lambda += 1;
+        steps += 1;
+        if steps > limit {
+            return None;
+        }
+    }
+
+    // find the start of the cycle
+    let mut tortoise = start.clone();
+    let mut hare = start;
+    for _ in 0..lambda {
+        hare = next(hare);
+    }
+    let mut mu = 0;
+    while tortoise != hare {
+        tortoise = next(tortoise);
+        hare = next(hare);
+        mu += 1;
     }
+    Some((mu, lambda))
 }
 
-fn main() -> Result<()> {
-    // part 1
-    let mut monkeys = Vec::new();
-    monkeys.push(Monkey::<u32> {
-        inspected: 0,
-        items: VecDeque::from([65, 58, 93, 57, 66]),
-        operation: Box::new(|v| v * 7),
-        test: Box::new(|v| if v % 19 == 0 { 6 } else { 4 }),
-    });
-    monkeys.push(Monkey::<u32> {
-        inspected: 0,
-        items: VecDeque::from([76, 97, 58, 72, 57, 92, 82]),
-        operation: Box::new(|v| v + 4),
-        test: Box::new(|v| if v % 3 == 0 { 7 } else { 5 }),
-    });
-    monkeys.push(Monkey::<u32> {
-        inspected: 0,
-        items: VecDeque::from([90, 89, 96]),
-        operation: Box::new(|v| v * 5),
-        test: Box::new(|v| if v % 13 == 0 { 5 } else { 1 }),
-    });
-    monkeys.push(Monkey::<u32> {
-        inspected: 0,
-        items: VecDeque::from([72, 63, 72, 99]),
-        operation: Box::new(|v| v * v),
-        test: Box::new(|v| if v % 17 == 0 { 0 } else { 4 }),
-    });
-    monkeys.push(Monkey::<u32> {
-        inspected: 0,
-        items: VecDeque::from([65]),
-        operation: Box::new(|v| v + 1),
-        test: Box::new(|v| if v % 2 == 0 { 6 } else { 2 }),
-    });
-    monkeys.push(Monkey::<u32> {
-        inspected: 0,
-        items: VecDeque::from([97, 71]),
-        operation: Box::new(|v| v + 8),
-        test: Box::new(|v| if v % 11 == 0 { 7 } else { 3 }),
-    });
-    monkeys.push(Monkey::<u32> {
-        inspected: 0,
-        items: VecDeque::from([83, 68, 88, 55, 87, 67]),
-        operation: Box::new(|v| v + 2),
-        test: Box::new(|v| if v % 5 == 0 { 2 } else { 1 }),
-    });
-    monkeys.push(Monkey::<u32> {
-        inspected: 0,
-        items: VecDeque::from([64, 81, 50, 96, 82, 53, 62, 92]),
-        operation: Box::new(|v| v + 5),
-        test: Box::new(|v| if v % 7 == 0 { 3 } else { 0 }),
-    });
-
-    for _ in 0..20 {
-        for m in 0..monkeys.len() {
-            while let Some(item) = monkeys[m].items.pop_front() {
-                let worry_level = (monkeys[m].operation)(&item) / 3;
-                let target = (monkeys[m].test)(&worry_level);
-                monkeys[m].inspected += 1;
-                assert!(target != m); // cannot send yourself
-                monkeys[target].items.push_back(worry_level);
+/// Periodicity of an item trajectory, in rounds
+#[derive(Debug, Clone, Copy)]
+struct ItemCycle {
+    start: u64,
+    length: u64,
+}
+
+/// Number of items inspected by each monkey after the given number of rounds without relief.
+/// Items move independently, and since their state is finite, their trajectories are
+/// eventually periodic: each item is followed until its cycle is found, then the inspections
+/// are extrapolated. Also return the cycle found for each item, if any.
+fn extrapolate_inspections<T: Worry + PartialEq>(
+    monkeys: &[Monkey<T>],
+    rounds: u64,
+) -> (Vec<usize>, Vec<Option<ItemCycle>>) {
+    let mut inspected = vec![0; monkeys.len()];
+    let mut cycles = Vec::new();
+    let play = |state, n: u64, inspected: &mut [usize]| {
+        (0..n).fold(state, |state, _| item_round(monkeys, state, inspected))
+    };
+
+    for (holder, m) in monkeys.iter().enumerate() {
+        for item in &m.items {
+            let start = (holder, item.clone());
+            let Some((mu, lambda)) = detect_cycle(monkeys, start.clone(), rounds) else {
+                // cheaper to play all the rounds
+                play(start, rounds, &mut inspected);
+                cycles.push(None);
+                continue;
+            };
+            cycles.push(Some(ItemCycle {
+                start: mu,
+                length: lambda,
+            }));
+
+            let cycle_start = play(start, mu.min(rounds), &mut inspected);
+            if rounds <= mu {
+                continue;
             }
+            let mut cycle_inspected = vec![0; monkeys.len()];
+            play(cycle_start.clone(), lambda, &mut cycle_inspected);
+            let remaining = rounds - mu;
+            for (total, per_cycle) in inspected.iter_mut().zip(cycle_inspected) {
+                *total += (remaining / lambda) as usize * per_cycle;
+            }
+            play(cycle_start, remaining % lambda, &mut inspected);
         }
     }
+    (inspected, cycles)
+}
 
-    monkeys.sort_by(|a, b| b.inspected.cmp(&a.inspected));
-    let monkey_business = monkeys[0].inspected * monkeys[1].inspected;
-    println!("{monkey_business}");
+fn monkey_business(inspected: &[usize]) -> u128 {
+    inspected
+        .iter()
+        .sorted_by_key(|&&inspected| std::cmp::Reverse(inspected))
+        .take(2)
+        .map(|&inspected| inspected as u128)
+        .product()
+}
 
-    // part 2
-    let mut monkeys = Vec::new();
-    monkeys.push(Monkey::<ItemRem> {
-        inspected: 0,
-        items: VecDeque::from(
-            [65, 58, 93, 57, 66]
-                .into_iter()
-                .map(ItemRem::new)
-                .collect_vec(),
-        ),
-        operation: Box::new(|&v| v * 7),
-        test: Box::new(|v| if v.0[0] == 0 { 6 } else { 4 }),
-    });
-    monkeys.push(Monkey::<ItemRem> {
-        inspected: 0,
-        items: VecDeque::from(
-            [76, 97, 58, 72, 57, 92, 82]
-                .into_iter()
-                .map(ItemRem::new)
-                .collect_vec(),
-        ),
-        operation: Box::new(|&v| v + 4),
-        test: Box::new(|v| if v.0[1] == 0 { 7 } else { 5 }),
-    });
-    monkeys.push(Monkey::<ItemRem> {
-        inspected: 0,
-        items: VecDeque::from([90, 89, 96].into_iter().map(ItemRem::new).collect_vec()),
-        operation: Box::new(|&v| v * 5),
-        test: Box::new(|v| if v.0[2] == 0 { 5 } else { 1 }),
-    });
-    monkeys.push(Monkey::<ItemRem> {
-        inspected: 0,
-        items: VecDeque::from([72, 63, 72, 99].into_iter().map(ItemRem::new).collect_vec()),
-        operation: Box::new(|&v| v * v),
-        test: Box::new(|v| if v.0[3] == 0 { 0 } else { 4 }),
-    });
-    monkeys.push(Monkey::<ItemRem> {
-        inspected: 0,
-        items: VecDeque::from([65].into_iter().map(ItemRem::new).collect_vec()),
-        operation: Box::new(|&v| v + 1),
-        test: Box::new(|v| if v.0[4] == 0 { 6 } else { 2 }),
-    });
-    monkeys.push(Monkey::<ItemRem> {
-        inspected: 0,
-        items: VecDeque::from([97, 71].into_iter().map(ItemRem::new).collect_vec()),
-        operation: Box::new(|&v| v + 8),
-        test: Box::new(|v| if v.0[5] == 0 { 7 } else { 3 }),
-    });
-    monkeys.push(Monkey::<ItemRem> {
-        inspected: 0,
-        items: VecDeque::from(
-            [83, 68, 88, 55, 87, 67]
-                .into_iter()
-                .map(ItemRem::new)
-                .collect_vec(),
-        ),
-        operation: Box::new(|&v| v + 2),
-        test: Box::new(|v| if v.0[6] == 0 { 2 } else { 1 }),
-    });
-    monkeys.push(Monkey::<ItemRem> {
-        inspected: 0,
-        items: VecDeque::from(
-            [64, 81, 50, 96, 82, 53, 62, 92]
-                .into_iter()
-                .map(ItemRem::new)
-                .collect_vec(),
-        ),
-        operation: Box::new(|&v| v + 5),
-        test: Box::new(|v| if v.0[7] == 0 { 3 } else { 0 }),
-    });
-
-    for _ in 0..10000 {
-        for m in 0..monkeys.len() {
-            while let Some(item) = monkeys[m].items.pop_front() {
-                let worry_level = (monkeys[m].operation)(&item);
-                let target = (monkeys[m].test)(&worry_level);
-                monkeys[m].inspected += 1;
-                assert!(target != m); // cannot send yourself
-                monkeys[target].items.push_back(worry_level);
+const INPUT: &str = include_str!("../data/11.txt");
+
+fn main() -> Result<()> {
+    let monkeys = parse_monkeys(INPUT)?;
+
+    // usage: 11 [--backend exact|bigint|rns|lcm] [--rounds N] [--relief D]
+    //           [--narrate N] [--summary ROUND,...]
+    //        11 [--backend rns|lcm] --extrapolate ROUNDS
+    // print the number of items inspected by each monkey and the monkey business,
+    // relief divides worry levels by D (1 for no relief), the first N rounds are narrated
+    // and inspection counts are summarized after the given rounds
+    let args = std::env::args().skip(1).collect_vec();
+    if !args.is_empty() {
+        let option = |name: &str| -> Result<Option<&str>> {
+            match args.iter().position(|a| a == name) {
+                Some(i) => Ok(Some(
+                    args.get(i + 1)
+                        .with_context(|| format!("{name} requires a value"))?,
+                )),
+                None => Ok(None),
+            }
+        };
+        let backend = match option("--backend")? {
+            Some(b) => Backend::try_from(b)?,
+            None => Backend::Rns,
+        };
+        if let Some(rounds) = option("--extrapolate")? {
+            let rounds = rounds.parse::<u64>()?;
+            let divisors = monkeys.iter().map(|m| m.divisor).collect::<Rc<[u64]>>();
+            let (inspected, cycles) = match backend {
+                Backend::Rns => extrapolate_inspections(
+                    &monkeys
+                        .iter()
+                        .map(|m| m.clone().map_items(|v| Rns::new(v, &divisors)))
+                        .collect_vec(),
+                    rounds,
+                ),
+                Backend::Lcm => {
+                    let modulus = divisors_lcm(&divisors)?;
+                    extrapolate_inspections(
+                        &monkeys
+                            .iter()
+                            .map(|m| m.clone().map_items(|v| ModLcm::new(v, modulus)))
+                            .collect_vec(),
+                        rounds,
+                    )
+                }
+                _ => bail!("extrapolation requires a finite representation: rns or lcm"),
+            };
+            for (id, cycle) in cycles.iter().enumerate() {
+                match cycle {
+                    Some(c) => println!(
+                        "item {id}: cycle of {} rounds from round {}",
+                        c.length, c.start
+                    ),
+                    None => println!("item {id}: no cycle within {rounds} rounds"),
+                }
             }
+            println!("{}", inspected.iter().join(" "));
+            println!("{}", monkey_business(&inspected));
+            return Ok(());
         }
+        let rounds = option("--rounds")?.map_or(Ok(10000), |r| r.parse::<usize>())?;
+        let relief = match option("--relief")?.map(|d| d.parse::<u64>()).transpose()? {
+            None | Some(1) => Relief::None,
+            Some(0) => bail!("cannot divide worry levels by 0"),
+            Some(d) => Relief::Divide(d),
+        };
+        let report = Report {
+            narrate: option("--narrate")?.map_or(Ok(0), |n| n.parse::<usize>())?,
+            summary: option("--summary")?
+                .map(|rounds| {
+                    rounds
+                        .split(',')
+                        .map(|r| r.parse::<usize>())
+                        .collect::<std::result::Result<Vec<_>, _>>()
+                })
+                .transpose()?
+                .unwrap_or_default(),
+        };
+        let inspected = inspections(&monkeys, backend, rounds, relief, Some(&report))?;
+        println!("{}", inspected.iter().join(" "));
+        println!("{}", monkey_business(&inspected));
+        return Ok(());
     }
 
-    monkeys.sort_by(|a, b| b.inspected.cmp(&a.inspected));
-    let monkey_business = monkeys[0].inspected * monkeys[1].inspected;
-    println!("{monkey_business}");
+    // part 1
+    let inspected = inspections(&monkeys, Backend::Exact, 20, Relief::Divide(3), None)?;
+    println!("{}", monkey_business(&inspected));
+
+    // part 2
+    let inspected = inspections(&monkeys, Backend::Rns, 10000, Relief::None, None)?;
+    println!("{}", monkey_business(&inspected));
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extrapolation_matches_play() {
+        let monkeys = parse_monkeys(INPUT).unwrap();
+        let divisors = monkeys.iter().map(|m| m.divisor).collect::<Rc<[u64]>>();
+        let modulus = divisors_lcm(&divisors).unwrap();
+        for rounds in [20, 1000, 10000] {
+            let played = inspections(&monkeys, Backend::Rns, rounds, Relief::None, None).unwrap();
+
+            let rns = monkeys
+                .iter()
+                .map(|m| m.clone().map_items(|v| Rns::new(v, &divisors)))
+                .collect_vec();
+            let (inspected, _) = extrapolate_inspections(&rns, rounds as u64);
+            assert_eq!(inspected, played, "rns, {rounds} rounds");
+
+            let lcm = monkeys
+                .iter()
+                .map(|m| m.clone().map_items(|v| ModLcm::new(v, modulus)))
+                .collect_vec();
+            let (inspected, _) = extrapolate_inspections(&lcm, rounds as u64);
+            assert_eq!(inspected, played, "lcm, {rounds} rounds");
+        }
+    }
+}
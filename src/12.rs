@@ -1,6 +1,5 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
-use petgraph::{algo::dijkstra, graphmap::DiGraphMap};
 use std::ops::{Index, IndexMut};
 
 struct Matrix<T> {
@@ -10,7 +9,7 @@ struct Matrix<T> {
 
 impl<T> Matrix<T> {
     fn new(raw_data: Vec<T>, row_length: usize) -> Self {
-        assert!(raw_data.len() % row_length == 0);
+        assert!(raw_data.len().is_multiple_of(row_length));
         Matrix {
             raw_data,
             row_length,
@@ -44,169 +43,391 @@ impl<T> IndexMut<usize> for Matrix<T> {
     }
 }
 
-const INPUT: &str = include_str!("../data/12.txt");
+mod grid_search {
+    use crate::Matrix;
+    use std::{
+        cmp::Reverse,
+        collections::{BinaryHeap, VecDeque},
+    };
 
-fn main() -> Result<()> {
-    // part 1
-    // convert into a matrix of char
-    let row_length = INPUT.lines().next().context("empty heightmap")?.len();
-    let flat_heightmap = INPUT
-        .lines()
-        .flat_map(|line| line.chars().collect_vec())
-        .collect_vec();
-    let heightmap = Matrix::new(flat_heightmap.clone(), row_length);
-
-    let rows = heightmap.rows();
-    let cols = heightmap.cols();
-
-    // find start location
-    let start = {
-        let mut start = (0, 0);
-        'outer: for i in 0..rows {
-            for j in 0..cols {
-                if heightmap[i][j] == 'S' {
-                    start = (i, j);
-                    break 'outer;
+    pub type Pos = (usize, usize);
+
+    /// Grid on which moving between two adjacent cells is allowed by `can_move`
+    pub struct Grid<F> {
+        pub rows: usize,
+        pub cols: usize,
+        pub can_move: F,
+    }
+
+    impl<F: Fn(Pos, Pos) -> bool> Grid<F> {
+        /// Cells reachable in one step from `pos`
+        pub fn neighbors(&self, pos: Pos) -> impl Iterator<Item = Pos> + '_ {
+            let (i, j) = pos;
+            [(-1, 0), (0, 1), (1, 0), (0, -1)]
+                .into_iter()
+                .filter_map(move |(di, dj)| {
+                    let next = (i.checked_add_signed(di)?, j.checked_add_signed(dj)?);
+                    (next.0 < self.rows && next.1 < self.cols).then_some(next)
+                })
+                .filter(move |&next| (self.can_move)(pos, next))
+        }
+
+        /// Follow `came_from` links back from `pos` to the source it was reached from
+        fn reconstruct_path(came_from: &Matrix<Option<Pos>>, mut pos: Pos) -> Vec<Pos> {
+            let mut path = vec![pos];
+            while let Some(prev) = came_from[pos.0][pos.1] {
+                path.push(prev);
+                pos = prev;
+            }
+            path.reverse();
+            path
+        }
+
+        /// Breadth-first search from all the sources at once, until a goal cell is reached.
+        /// Return the shortest path, from a source to a goal.
+        pub fn bfs(&self, sources: &[Pos], is_goal: impl Fn(Pos) -> bool) -> Option<Vec<Pos>> {
+            let mut came_from = Matrix::new(vec![None; self.rows * self.cols], self.cols);
+            let mut visited = Matrix::new(vec![false; self.rows * self.cols], self.cols);
+            let mut queue = VecDeque::new();
+            for &source in sources {
+                if !visited[source.0][source.1] {
+                    visited[source.0][source.1] = true;
+                    queue.push_back(source);
                 }
             }
+            while let Some(pos) = queue.pop_front() {
+                if is_goal(pos) {
+                    return Some(Self::reconstruct_path(&came_from, pos));
+                }
+                for next in self.neighbors(pos) {
+                    if !visited[next.0][next.1] {
+                        visited[next.0][next.1] = true;
+                        came_from[next.0][next.1] = Some(pos);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            None
         }
-        start
-    };
 
-    // find target location
-    let goal = {
-        let mut goal = (0, 0);
-        'outer: for i in 0..rows {
-            for j in 0..cols {
-                if heightmap[i][j] == 'E' {
-                    goal = (i, j);
-                    break 'outer;
+        /// Length of the shortest path from any of the sources to every cell, None if
+        /// unreachable
+        pub fn distance_field(&self, sources: &[Pos]) -> Matrix<Option<usize>> {
+            let mut distances = Matrix::new(vec![None; self.rows * self.cols], self.cols);
+            let mut queue = VecDeque::new();
+            for &source in sources {
+                if distances[source.0][source.1].is_none() {
+                    distances[source.0][source.1] = Some(0);
+                    queue.push_back(source);
+                }
+            }
+            while let Some(pos) = queue.pop_front() {
+                let distance = distances[pos.0][pos.1].unwrap();
+                for next in self.neighbors(pos) {
+                    if distances[next.0][next.1].is_none() {
+                        distances[next.0][next.1] = Some(distance + 1);
+                        queue.push_back(next);
+                    }
                 }
             }
+            distances
         }
-        goal
-    };
 
-    // rewrite heighmap as matrix of int
-    let flat_heightmap = flat_heightmap
-        .into_iter()
-        .map(|c| {
-            let c = match c {
-                'E' => 'z',
-                'S' => 'a',
-                _ => c,
-            };
-            c as i32 - 'a' as i32
-        })
-        .collect_vec();
-    let heightmap = Matrix::new(flat_heightmap, row_length);
-
-    // list possible transitions
-    let mut edges = Vec::new();
-    for i in 0..rows {
-        for j in 0..cols {
-            let moves = [(-1, 0), (0, 1), (1, 0), (0, -1)];
-            for m in moves.into_iter() {
-                let m_i = i as i32 + m.0;
-                let m_j = j as i32 + m.1;
-                if m_i < 0 || m_i >= rows as i32 || m_j < 0 || m_j >= cols as i32 {
-                    continue; // outside map
+        /// A* search from all the sources at once to the goal, guided by the Manhattan
+        /// distance. Return the shortest path, from a source to the goal.
+        pub fn astar(&self, sources: &[Pos], goal: Pos) -> Option<Vec<Pos>> {
+            let heuristic = |pos: Pos| pos.0.abs_diff(goal.0) + pos.1.abs_diff(goal.1);
+            let mut came_from = Matrix::new(vec![None; self.rows * self.cols], self.cols);
+            let mut cost = Matrix::new(vec![usize::MAX; self.rows * self.cols], self.cols);
+            let mut open = BinaryHeap::new();
+            for &source in sources {
+                cost[source.0][source.1] = 0;
+                open.push(Reverse((heuristic(source), 0, source)));
+            }
+            while let Some(Reverse((_, g, pos))) = open.pop() {
+                if pos == goal {
+                    return Some(Self::reconstruct_path(&came_from, pos));
+                }
+                if g > cost[pos.0][pos.1] {
+                    continue; // outdated entry
                 }
-                let (m_i, m_j) = (m_i as usize, m_j as usize);
-                if heightmap[i][j] + 1 >= heightmap[m_i][m_j] {
-                    edges.push(((i, j), (m_i, m_j)));
+                for next in self.neighbors(pos) {
+                    let next_g = g + 1;
+                    if next_g < cost[next.0][next.1] {
+                        cost[next.0][next.1] = next_g;
+                        came_from[next.0][next.1] = Some(pos);
+                        open.push(Reverse((next_g + heuristic(next), next_g, next)));
+                    }
                 }
             }
+            None
         }
     }
+}
+use grid_search::{Grid, Pos};
 
-    // create and explore graph from start to goal
-    let graph = DiGraphMap::<_, ()>::from_edges(edges);
-    let distance_map = dijkstra(&graph, start, Some(goal), |_| 1);
-    println!("{:?}", distance_map[&goal]);
+/// Which climbs and descents are allowed between adjacent cells
+#[derive(Debug, Clone, Copy)]
+struct ClimbRule {
+    max_climb: i32,
+    // unlimited if None
+    max_drop: Option<i32>,
+}
 
-    // part 2
-    // convert into a matrix of char
-    let row_length = INPUT.lines().next().context("empty heightmap")?.len();
-    let flat_heightmap = INPUT
-        .lines()
-        .flat_map(|line| line.chars().collect_vec())
-        .collect_vec();
-    let heightmap = Matrix::new(flat_heightmap.clone(), row_length);
-
-    let rows = heightmap.rows();
-    let cols = heightmap.cols();
-
-    // find start location candidates
-    let starts = {
-        let mut starts = Vec::new();
-        for i in 0..rows {
-            for j in 0..cols {
-                if heightmap[i][j] == 'S' || heightmap[i][j] == 'a' {
-                    starts.push((i, j));
-                }
+impl Default for ClimbRule {
+    fn default() -> Self {
+        Self {
+            max_climb: 1,
+            max_drop: None,
+        }
+    }
+}
+
+impl ClimbRule {
+    fn allows(&self, from: i32, to: i32) -> bool {
+        to <= from + self.max_climb && self.max_drop.is_none_or(|d| to >= from - d)
+    }
+}
+
+struct Heightmap {
+    heights: Matrix<i32>,
+    start: Pos,
+    goal: Pos,
+}
+
+impl Heightmap {
+    fn parse(input: &str) -> Result<Self> {
+        // convert into a matrix of char
+        let row_length = input.lines().next().context("empty heightmap")?.len();
+        let flat_heightmap = input
+            .lines()
+            .flat_map(|line| line.chars().collect_vec())
+            .collect_vec();
+        if !flat_heightmap.len().is_multiple_of(row_length) {
+            bail!("heightmap is not a rectangle");
+        }
+        let chars = Matrix::new(flat_heightmap.clone(), row_length);
+
+        let find = |c: char| {
+            (0..chars.rows())
+                .cartesian_product(0..chars.cols())
+                .find(|&(i, j)| chars[i][j] == c)
+                .with_context(|| format!("no '{c}' in heightmap"))
+        };
+        let start = find('S')?;
+        let goal = find('E')?;
+
+        // rewrite heighmap as matrix of int
+        let flat_heightmap = flat_heightmap
+            .into_iter()
+            .map(|c| {
+                let c = match c {
+                    'E' => 'z',
+                    'S' => 'a',
+                    _ => c,
+                };
+                c as i32 - 'a' as i32
+            })
+            .collect_vec();
+        Ok(Self {
+            heights: Matrix::new(flat_heightmap, row_length),
+            start,
+            goal,
+        })
+    }
+
+    fn grid(&self, rule: ClimbRule) -> Grid<impl Fn(Pos, Pos) -> bool + '_> {
+        Grid {
+            rows: self.heights.rows(),
+            cols: self.heights.cols(),
+            can_move: move |from: Pos, to: Pos| {
+                rule.allows(self.heights[from.0][from.1], self.heights[to.0][to.1])
+            },
+        }
+    }
+
+    /// Grid with all the moves reversed, to search from the goal
+    fn reverse_grid(&self, rule: ClimbRule) -> Grid<impl Fn(Pos, Pos) -> bool + '_> {
+        Grid {
+            rows: self.heights.rows(),
+            cols: self.heights.cols(),
+            can_move: move |from: Pos, to: Pos| {
+                rule.allows(self.heights[to.0][to.1], self.heights[from.0][from.1])
+            },
+        }
+    }
+
+    /// Draw the route on the map as in the puzzle: each step is an arrow pointing to the
+    /// next one, the goal is 'E'
+    fn render_route(&self, path: &[Pos]) -> String {
+        let mut map = Matrix::new(
+            vec!['.'; self.heights.rows() * self.heights.cols()],
+            self.heights.cols(),
+        );
+        for (from, to) in path.iter().tuple_windows() {
+            map[from.0][from.1] = match (to.0 as i32 - from.0 as i32, to.1 as i32 - from.1 as i32) {
+                (-1, 0) => '^',
+                (1, 0) => 'v',
+                (0, -1) => '<',
+                (0, 1) => '>',
+                _ => panic!("not adjacent cells: {from:?} {to:?}"),
+            };
+        }
+        if let Some(end) = path.last() {
+            map[end.0][end.1] = 'E';
+        }
+        (0..map.rows())
+            .map(|i| map[i].iter().collect::<String>())
+            .join("\n")
+    }
+
+    /// All the cells at the lowest elevation
+    fn lowest_cells(&self) -> Vec<Pos> {
+        (0..self.heights.rows())
+            .cartesian_product(0..self.heights.cols())
+            .filter(|&(i, j)| self.heights[i][j] == 0)
+            .collect()
+    }
+}
+
+fn distances_to_csv(distances: &Matrix<Option<usize>>) -> String {
+    let mut csv = String::new();
+    for i in 0..distances.rows() {
+        csv += &distances[i]
+            .iter()
+            .map(|d| d.map(|d| d.to_string()).unwrap_or_default())
+            .join(",");
+        csv += "\n";
+    }
+    csv
+}
+
+/// Encode distances as a binary PPM image, from blue (close) to red (far). Unreachable cells
+/// are black.
+fn distances_to_ppm(distances: &Matrix<Option<usize>>) -> Vec<u8> {
+    let max_distance = distances
+        .raw_data
+        .iter()
+        .flatten()
+        .max()
+        .copied()
+        .unwrap_or(0);
+    let mut image = format!("P6\n{} {}\n255\n", distances.cols(), distances.rows()).into_bytes();
+    for d in distances.raw_data.iter() {
+        let pixel = match d {
+            Some(d) => {
+                let t = if max_distance > 0 {
+                    *d as f64 / max_distance as f64
+                } else {
+                    0.0
+                };
+                [(255.0 * t) as u8, 0, (255.0 * (1.0 - t)) as u8]
             }
+            None => [0, 0, 0],
+        };
+        image.extend_from_slice(&pixel);
+    }
+    image
+}
+
+const INPUT: &str = include_str!("../data/12.txt");
+
+fn main() -> Result<()> {
+    // usage: 12 [--astar] [--max-climb N] [--max-drop N] [--route]
+    //           [--distances-csv FILE] [--distances-image FILE]
+    let args = std::env::args().skip(1).collect_vec();
+    let option = |name: &str| -> Result<Option<&str>> {
+        match args.iter().position(|a| a == name) {
+            Some(i) => {
+                let value = args
+                    .get(i + 1)
+                    .with_context(|| format!("{name} requires a value"))?;
+                Ok(Some(value))
+            }
+            None => Ok(None),
         }
-        starts
+    };
+    let int_option = |name: &str| -> Result<Option<i32>> {
+        Ok(option(name)?.map(|v| v.parse::<i32>()).transpose()?)
+    };
+    let show_route = args.iter().any(|a| a == "--route");
+    let use_astar = args.iter().any(|a| a == "--astar");
+    let default_rule = ClimbRule::default();
+    let rule = ClimbRule {
+        max_climb: int_option("--max-climb")?.unwrap_or(default_rule.max_climb),
+        max_drop: int_option("--max-drop")?.or(default_rule.max_drop),
     };
 
-    // find target location
-    let goal = {
-        let mut goal = (0, 0);
-        'outer: for i in 0..rows {
-            for j in 0..cols {
-                if heightmap[i][j] == 'E' {
-                    goal = (i, j);
-                    break 'outer;
-                }
-            }
+    let heightmap = Heightmap::parse(INPUT)?;
+    let grid = heightmap.grid(rule);
+    let search = |sources: &[Pos]| {
+        if use_astar {
+            grid.astar(sources, heightmap.goal)
+        } else {
+            grid.bfs(sources, |pos| pos == heightmap.goal)
         }
-        goal
     };
 
-    // rewrite heighmap as matrix of int
-    let flat_heightmap = flat_heightmap
-        .into_iter()
-        .map(|c| {
-            let c = match c {
-                'E' => 'z',
-                'S' => 'a',
-                _ => c,
-            };
-            c as i32 - 'a' as i32
-        })
-        .collect_vec();
-    let heightmap = Matrix::new(flat_heightmap, row_length);
-
-    // list possible transitions
-    let mut edges = Vec::new();
-    for i in 0..rows {
-        for j in 0..cols {
-            let moves = [(-1, 0), (0, 1), (1, 0), (0, -1)];
-            for m in moves.into_iter() {
-                let m_i = i as i32 + m.0;
-                let m_j = j as i32 + m.1;
-                if m_i < 0 || m_i >= rows as i32 || m_j < 0 || m_j >= cols as i32 {
-                    continue; // outside map
-                }
-                let (m_i, m_j) = (m_i as usize, m_j as usize);
-                if heightmap[i][j] + 1 >= heightmap[m_i][m_j] {
-                    // going reverse
-                    edges.push(((m_i, m_j), (i, j)));
-                }
-            }
+    let csv_file = option("--distances-csv")?;
+    let image_file = option("--distances-image")?;
+    if csv_file.is_some() || image_file.is_some() {
+        let distances = heightmap
+            .reverse_grid(rule)
+            .distance_field(&[heightmap.goal]);
+        if let Some(file) = csv_file {
+            std::fs::write(file, distances_to_csv(&distances))?;
+        }
+        if let Some(file) = image_file {
+            std::fs::write(file, distances_to_ppm(&distances))?;
         }
     }
 
-    // create and explore graph from goal to everywhere
-    let graph = DiGraphMap::<_, ()>::from_edges(edges);
-    let distance_map = dijkstra(&graph, goal, None, |_| 1);
-    let min_distance = distance_map
-        .iter()
-        .filter_map(|(coord, distance)| starts.contains(coord).then_some(distance))
-        .min()
-        .context("no path found")?;
-    println!("{min_distance}");
+    // part 1
+    let path = search(&[heightmap.start]).context("no path found")?;
+    println!("{}", path.len() - 1);
+    if show_route {
+        println!("{}\n", heightmap.render_route(&path));
+    }
+
+    // part 2
+    let path = search(&heightmap.lowest_cells()).context("no path found")?;
+    println!("{}", path.len() - 1);
+    if show_route {
+        println!("{}", heightmap.render_route(&path));
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_agree() {
+        let heightmap = Heightmap::parse(INPUT).unwrap();
+        let rule = ClimbRule::default();
+        let grid = heightmap.grid(rule);
+        let distances = heightmap
+            .reverse_grid(rule)
+            .distance_field(&[heightmap.goal]);
+        let height = |pos: &Pos| heightmap.heights[pos.0][pos.1];
+
+        for sources in [vec![heightmap.start], heightmap.lowest_cells()] {
+            let bfs = grid.bfs(&sources, |pos| pos == heightmap.goal).unwrap();
+            let astar = grid.astar(&sources, heightmap.goal).unwrap();
+            assert_eq!(bfs.len(), astar.len());
+            for path in [&bfs, &astar] {
+                assert!(sources.contains(&path[0]));
+                assert_eq!(path.last(), Some(&heightmap.goal));
+                for (from, to) in path.iter().tuple_windows() {
+                    assert_eq!(from.0.abs_diff(to.0) + from.1.abs_diff(to.1), 1);
+                    assert!(rule.allows(height(from), height(to)));
+                }
+            }
+            let shortest = sources.iter().filter_map(|s| distances[s.0][s.1]).min();
+            // the distance field from the goal gives the same length, at `start` for part 1
+            assert_eq!(shortest, Some(bfs.len() - 1));
+        }
+    }
+}
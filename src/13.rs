@@ -1,39 +1,359 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, fmt::Display};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
-use nom::{
-    branch::alt,
-    character::complete::{char, i32},
-    combinator::{cut, map},
-    multi::separated_list0,
-    sequence::{preceded, terminated},
-    IResult,
-};
 
 #[derive(Debug, Clone)]
-enum PacketData {
+pub enum PacketData {
     PInt(i32),
     PList(Vec<PacketData>),
 }
 use PacketData::*;
 
-fn parse_list(input: &str) -> IResult<&str, Vec<PacketData>> {
-    preceded(
-        char('['),
-        cut(terminated(
-            separated_list0(char(','), parse_packet),
-            char(']'),
-        )),
-    )(input)
+mod parser {
+    use crate::{
+        Node,
+        PacketData::{self, *},
+    };
+    use anyhow::{anyhow, Result};
+    use nom::{
+        branch::alt,
+        character::complete::{char, i32, space0},
+        combinator::{complete, cut, eof, map},
+        error::{context, convert_error, ContextError, ParseError, VerboseError},
+        multi::separated_list0,
+        sequence::{delimited, preceded, terminated},
+        Finish, IResult,
+    };
+
+    fn _list<'a, E>(input: &'a str) -> IResult<&'a str, Vec<PacketData>, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context(
+            "list",
+            preceded(
+                char('['),
+                cut(terminated(separated_list0(char(','), _packet), char(']'))),
+            ),
+        )(input)
+    }
+
+    fn _packet<'a, E>(input: &'a str) -> IResult<&'a str, PacketData, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context("integer or list", alt((map(i32, PInt), map(_list, PList))))(input)
+    }
+
+    fn _root<'a, E>(input: &'a str) -> IResult<&'a str, PacketData, E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (input, packet) = delimited(space0, map(_list, PList), space0)(input)?;
+        context("end of packet", eof)(input)?;
+        Ok((input, packet))
+    }
+
+    /// Parse a single packet, which must be a list, possibly surrounded by spaces. Errors
+    /// report the column of the failure.
+    pub fn packet(input: &str) -> Result<PacketData> {
+        let (_, packet) = complete(_root::<VerboseError<&str>>)(input)
+            .finish()
+            .map_err(|e| error(input, e))?;
+        Ok(packet)
+    }
+
+    // same grammar as above, but the nodes are appended to an arena instead of building
+    // nested vectors
+
+    fn _arena_list<'a, E>(input: &'a str, nodes: &mut Vec<Node>) -> IResult<&'a str, (), E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context("list", |input: &'a str| {
+            let (input, _) = char('[')(input)?;
+            let start = nodes.len();
+            nodes.push(Node::List { span: 0 });
+            let (input, _) = cut(terminated(
+                separated_list0(char(','), |input| _arena_packet(input, nodes)),
+                char(']'),
+            ))(input)?;
+            nodes[start] = Node::List {
+                span: nodes.len() - start,
+            };
+            Ok((input, ()))
+        })(input)
+    }
+
+    fn _arena_packet<'a, E>(input: &'a str, nodes: &mut Vec<Node>) -> IResult<&'a str, (), E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        context("integer or list", |input: &'a str| {
+            match i32::<_, E>(input) {
+                Ok((input, i)) => {
+                    nodes.push(Node::Int(i));
+                    Ok((input, ()))
+                }
+                Err(nom::Err::Error(_)) => _arena_list(input, nodes),
+                Err(e) => Err(e),
+            }
+        })(input)
+    }
+
+    fn _arena_root<'a, E>(input: &'a str, nodes: &mut Vec<Node>) -> IResult<&'a str, (), E>
+    where
+        E: ParseError<&'a str> + ContextError<&'a str>,
+    {
+        let (input, _) = delimited(space0, |input| _arena_list(input, nodes), space0)(input)?;
+        context("end of packet", eof)(input)?;
+        Ok((input, ()))
+    }
+
+    /// Same as `packet`, appending the nodes of the packet to `nodes` in pre-order. Nodes
+    /// may have been appended when an error is returned.
+    pub fn arena_packet(input: &str, nodes: &mut Vec<Node>) -> Result<()> {
+        complete(|input| _arena_root::<VerboseError<&str>>(input, nodes))(input)
+            .finish()
+            .map_err(|e| error(input, e))?;
+        Ok(())
+    }
+
+    /// Error on a single line: the caller knows the line number, so only the column is given
+    fn error(input: &str, e: VerboseError<&str>) -> anyhow::Error {
+        let column = e
+            .errors
+            .first()
+            .map_or(1, |(rest, _)| input.len() - rest.len() + 1);
+        let details = convert_error(input, e)
+            .replace(" at line 1, ", " ")
+            .replace(" at line 1:", "");
+        anyhow!("column {column}: {details}")
+    }
+}
+
+/// Parse the pairs of packets. Pairs are separated by one or more blank lines, and each pair
+/// must have exactly 2 packets.
+fn parse_pairs(input: &str) -> Result<Vec<(PacketData, PacketData)>> {
+    let lines = input.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let mut pairs = Vec::new();
+    for (is_blank, group) in &lines.group_by(|(_, line)| line.trim().is_empty()) {
+        if is_blank {
+            continue;
+        }
+        let packets = group
+            .map(|(nb, line)| {
+                let packet = parser::packet(line)
+                    .with_context(|| format!("Failed to parse packet at line {nb}"))?;
+                Ok((nb, packet))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        match <[_; 2]>::try_from(packets) {
+            Ok([(_, left), (_, right)]) => pairs.push((left, right)),
+            Err(packets) => bail!(
+                "Expected a pair of packets at line {}, found {} packet(s)",
+                packets[0].0,
+                packets.len()
+            ),
+        }
+    }
+    Ok(pairs)
 }
 
-fn parse_packet(input: &str) -> IResult<&str, PacketData> {
-    alt((map(i32, PInt), map(parse_list, PList)))(input)
+impl Display for PacketData {
+    /// Same bracket syntax as the input, so the output can be parsed back
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PInt(i) => write!(f, "{i}"),
+            PList(items) => write!(f, "[{}]", items.iter().join(",")),
+        }
+    }
 }
 
-fn parse_root(input: &str) -> IResult<&str, PacketData> {
-    map(parse_list, PList)(input)
+impl PacketData {
+    /// Multi-line representation, one item per line and nested lists indented by `indent`
+    /// spaces per level. Empty lists and lists of integers only are kept on one line.
+    fn pretty(&self, indent: usize) -> String {
+        let mut out = String::new();
+        self.pretty_into(&mut out, indent, 0);
+        out
+    }
+
+    fn pretty_into(&self, out: &mut String, indent: usize, depth: usize) {
+        match self {
+            PList(items) if items.iter().any(|item| matches!(item, PList(_))) => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(&" ".repeat(indent * (depth + 1)));
+                    item.pretty_into(out, indent, depth + 1);
+                    if i + 1 < items.len() {
+                        out.push(',');
+                    }
+                    out.push('\n');
+                }
+                out.push_str(&" ".repeat(indent * depth));
+                out.push(']');
+            }
+            _ => out.push_str(&self.to_string()),
+        }
+    }
+}
+
+impl From<&PacketData> for serde_json::Value {
+    fn from(packet: &PacketData) -> Self {
+        match packet {
+            PInt(i) => serde_json::Value::from(*i),
+            PList(items) => serde_json::Value::Array(items.iter().map(Self::from).collect()),
+        }
+    }
+}
+
+impl TryFrom<&serde_json::Value> for PacketData {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &serde_json::Value) -> std::result::Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::Number(n) => {
+                let i = n
+                    .as_i64()
+                    .and_then(|i| i32::try_from(i).ok())
+                    .with_context(|| format!("not a packet integer: {n}"))?;
+                Ok(PInt(i))
+            }
+            serde_json::Value::Array(items) => Ok(PList(
+                items
+                    .iter()
+                    .map(PacketData::try_from)
+                    .collect::<Result<_>>()?,
+            )),
+            v => bail!("not a packet value: {v}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Side::Left => write!(f, "left"),
+            Side::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// Rule that decided the order of two packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    /// The integer on this side is smaller
+    Smaller(Side),
+    /// The list on this side has no more items
+    RanOut(Side),
+}
+
+impl Rule {
+    fn ordering(&self) -> Ordering {
+        match self {
+            Rule::Smaller(Side::Left) | Rule::RanOut(Side::Left) => Ordering::Less,
+            Rule::Smaller(Side::Right) | Rule::RanOut(Side::Right) => Ordering::Greater,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Compare(PacketData, PacketData),
+    /// The integer on one side is converted to a list to be compared with a list
+    Promote(Side, i32),
+    Decided(Rule),
+}
+
+/// Step-by-step comparison of two packets. Each step is given with its nesting depth.
+#[derive(Debug, Clone)]
+struct Explanation {
+    steps: Vec<(usize, Step)>,
+    ordering: Ordering,
+}
+
+impl Display for Explanation {
+    /// Same format as in the puzzle description
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (depth, step) in &self.steps {
+            write!(f, "{}- ", "  ".repeat(*depth))?;
+            match step {
+                Step::Compare(left, right) => writeln!(f, "Compare {left} vs {right}")?,
+                Step::Promote(side, i) => writeln!(
+                    f,
+                    "Mixed types; convert {side} to [{i}] and retry comparison"
+                )?,
+                Step::Decided(rule) => {
+                    let reason = match rule {
+                        Rule::Smaller(Side::Left) => "Left side is smaller",
+                        Rule::Smaller(Side::Right) => "Right side is smaller",
+                        Rule::RanOut(Side::Left) => "Left side ran out of items",
+                        Rule::RanOut(Side::Right) => "Right side ran out of items",
+                    };
+                    let order = match rule.ordering() {
+                        Ordering::Less => "in the right order",
+                        _ => "not in the right order",
+                    };
+                    writeln!(f, "{reason}, so inputs are {order}")?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PacketData {
+    /// Compare with another packet, recording every step of the comparison. The resulting
+    /// ordering is always the same as `cmp`.
+    fn explain(&self, other: &Self) -> Explanation {
+        let mut steps = Vec::new();
+        let ordering = self.explain_into(other, 0, &mut steps);
+        Explanation { steps, ordering }
+    }
+
+    fn explain_into(&self, other: &Self, depth: usize, steps: &mut Vec<(usize, Step)>) -> Ordering {
+        steps.push((depth, Step::Compare(self.clone(), other.clone())));
+        let decide = |steps: &mut Vec<(usize, Step)>, rule: Rule| {
+            steps.push((depth + 1, Step::Decided(rule)));
+            rule.ordering()
+        };
+        match (self, other) {
+            (PInt(i), PInt(j)) => match i.cmp(j) {
+                Ordering::Less => decide(steps, Rule::Smaller(Side::Left)),
+                Ordering::Greater => decide(steps, Rule::Smaller(Side::Right)),
+                Ordering::Equal => Ordering::Equal,
+            },
+            (PInt(i), PList(_)) => {
+                steps.push((depth + 1, Step::Promote(Side::Left, *i)));
+                PList(vec![PInt(*i)]).explain_into(other, depth + 1, steps)
+            }
+            (PList(_), PInt(j)) => {
+                steps.push((depth + 1, Step::Promote(Side::Right, *j)));
+                self.explain_into(&PList(vec![PInt(*j)]), depth + 1, steps)
+            }
+            (PList(vl), PList(vr)) => {
+                for (left_item, right_item) in vl.iter().zip(vr) {
+                    let res = left_item.explain_into(right_item, depth + 1, steps);
+                    if res != Ordering::Equal {
+                        return res;
+                    }
+                }
+                match vl.len().cmp(&vr.len()) {
+                    Ordering::Less => decide(steps, Rule::RanOut(Side::Left)),
+                    Ordering::Greater => decide(steps, Rule::RanOut(Side::Right)),
+                    Ordering::Equal => Ordering::Equal,
+                }
+            }
+        }
+    }
 }
 
 impl Ord for PacketData {
@@ -99,71 +419,302 @@ impl PartialEq for PacketData {
 
 impl Eq for PacketData {}
 
+#[derive(Debug, Clone, Copy)]
+enum Node {
+    Int(i32),
+    /// A list followed by its items. `span` is the number of nodes of the whole list,
+    /// including itself.
+    List {
+        span: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PacketId(usize);
+
+/// Packets stored flat in a single buffer, in pre-order, to avoid allocating a vector for
+/// every nested list
+#[derive(Debug, Default)]
+struct PacketArena {
+    nodes: Vec<Node>,
+}
+
+impl PacketArena {
+    /// Parse a packet straight into the arena, with the same errors as `parser::packet`
+    fn push(&mut self, input: &str) -> Result<PacketId> {
+        let root = self.nodes.len();
+        if let Err(e) = parser::arena_packet(input, &mut self.nodes) {
+            self.nodes.truncate(root);
+            return Err(e);
+        }
+        Ok(PacketId(root))
+    }
+
+    fn span(&self, node: usize) -> usize {
+        match self.nodes[node] {
+            Node::Int(_) => 1,
+            Node::List { span } => span,
+        }
+    }
+
+    /// Items of a list node. An integer node is seen as a list containing only itself.
+    fn items(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        let end = node + self.span(node);
+        let first = match self.nodes[node] {
+            Node::Int(_) => node,
+            Node::List { .. } => node + 1,
+        };
+        let next = move |&i: &usize| Some(i + self.span(i)).filter(|&i| i < end);
+        std::iter::successors(Some(first).filter(|&i| i < end), next)
+    }
+
+    fn cmp_nodes(&self, left: usize, right: usize) -> Ordering {
+        if let (Node::Int(i), Node::Int(j)) = (self.nodes[left], self.nodes[right]) {
+            return i.cmp(&j);
+        }
+        let mut vl = self.items(left);
+        let mut vr = self.items(right);
+        loop {
+            match (vl.next(), vr.next()) {
+                (None, None) => break Ordering::Equal,
+                (None, Some(_)) => break Ordering::Less,
+                (Some(_), None) => break Ordering::Greater,
+                (Some(left_item), Some(right_item)) => {
+                    let res = self.cmp_nodes(left_item, right_item);
+                    if res != Ordering::Equal {
+                        break res;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Same ordering as `PacketData::cmp`
+    fn cmp(&self, left: PacketId, right: PacketId) -> Ordering {
+        self.cmp_nodes(left.0, right.0)
+    }
+}
+
+/// 1-based position of each divider if all the packets and dividers were sorted. Instead of
+/// sorting, the packets less than each divider are counted in a single pass.
+fn divider_positions<P>(
+    packets: impl IntoIterator<Item = P>,
+    dividers: &[P],
+    cmp: impl Fn(&P, &P) -> Ordering,
+) -> Vec<usize> {
+    let mut positions = dividers
+        .iter()
+        .map(|divider| 1 + dividers.iter().filter(|d| cmp(d, divider).is_lt()).count())
+        .collect_vec();
+    for packet in packets {
+        for (position, divider) in positions.iter_mut().zip(dividers) {
+            if cmp(&packet, divider).is_lt() {
+                *position += 1;
+            }
+        }
+    }
+    positions
+}
+
+fn decoder_key<P>(
+    packets: impl IntoIterator<Item = P>,
+    dividers: &[P],
+    cmp: impl Fn(&P, &P) -> Ordering,
+) -> usize {
+    divider_positions(packets, dividers, cmp).iter().product()
+}
+
 const INPUT: &str = include_str!("../data/13.txt");
+const DIVIDERS: [&str; 2] = ["[[2]]", "[[6]]"];
 
 fn main() -> Result<()> {
-    // part 1
-    let mut sum = 0;
-    for (i, (left, right)) in INPUT
-        .lines()
-        .chunks(3)
-        .into_iter()
-        .map(|chunk| chunk.take(2).collect_tuple::<(_, _)>().unwrap())
-        .enumerate()
-    {
-        let (_, left_packet) = parse_root(left)
-            // Note: nom errors keep reference to the original &str, which cannot leave the function scope.
-            // Hence the .to_owned() call to duplicate the contained strings.
-            .map_err(|err| err.to_owned())?;
-        let (_, right_packet) = parse_root(right)
-            // Note: nom errors keep reference to the original &str, which cannot leave the function scope.
-            // Hence the .to_owned() call to duplicate the contained strings.
-            .map_err(|err| err.to_owned())?;
+    let pairs = parse_pairs(INPUT)?;
 
-        if left_packet < right_packet {
-            sum += i + 1;
+    // usage: 13 [--pretty | --json | --explain [PAIR]] [--divider PACKET]...
+    // dump the packets of the input before solving, indented or as a JSON array of pairs,
+    // or explain how the pairs (all of them by default) are compared
+    // the decoder key uses the given divider packets instead of [[2]] and [[6]]
+    let args = std::env::args().skip(1).collect_vec();
+    let mut divider_args = args
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| *a == "--divider")
+        .map(|(i, _)| {
+            args.get(i + 1)
+                .map(String::as_str)
+                .context("--divider requires a packet")
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if divider_args.is_empty() {
+        divider_args = DIVIDERS.to_vec();
+    }
+    if let Some(i) = args.iter().position(|a| a == "--explain") {
+        let selected = args.get(i + 1).map(|p| p.parse::<usize>()).transpose()?;
+        for (i, (left, right)) in pairs.iter().enumerate() {
+            if selected.is_some_and(|p| p != i + 1) {
+                continue;
+            }
+            let explanation = left.explain(right);
+            debug_assert_eq!(explanation.ordering, left.cmp(right));
+            println!("== Pair {} ==\n{explanation}", i + 1);
+        }
+    }
+    if args.iter().any(|a| a == "--json") {
+        let json = serde_json::Value::Array(
+            pairs
+                .iter()
+                .map(|(left, right)| serde_json::Value::Array(vec![left.into(), right.into()]))
+                .collect(),
+        );
+        println!("{}", serde_json::to_string_pretty(&json)?);
+    } else if args.iter().any(|a| a == "--pretty") {
+        for (i, (left, right)) in pairs.iter().enumerate() {
+            println!("== Pair {} ==", i + 1);
+            println!("{}\n{}\n", left.pretty(2), right.pretty(2));
         }
     }
+
+    // part 1
+    let sum: usize = pairs
+        .iter()
+        .enumerate()
+        .filter(|(_, (left, right))| left < right)
+        .map(|(i, _)| i + 1)
+        .sum();
     println!("{sum}");
 
-    // part 2
-    let mut packets = Vec::new();
-    for (left, right) in INPUT
+    // part 2, the packets are parsed again straight into the arena
+    let mut arena = PacketArena::default();
+    let packets = INPUT
         .lines()
-        .chunks(3)
-        .into_iter()
-        .map(|chunk| chunk.take(2).collect_tuple::<(_, _)>().unwrap())
-    {
-        let (_, left_packet) = parse_root(left)
-            // Note: nom errors keep reference to the original &str, which cannot leave the function scope.
-            // Hence the .to_owned() call to duplicate the contained strings.
-            .map_err(|err| err.to_owned())?;
-        let (_, right_packet) = parse_root(right)
-            // Note: nom errors keep reference to the original &str, which cannot leave the function scope.
-            // Hence the .to_owned() call to duplicate the contained strings.
-            .map_err(|err| err.to_owned())?;
-        packets.push(left_packet);
-        packets.push(right_packet);
-    }
-    let divider_2 = PList(vec![PList(vec![PInt(2)])]);
-    let divider_6 = PList(vec![PList(vec![PInt(6)])]);
-
-    packets.push(divider_2.clone());
-    packets.push(divider_6.clone());
-    packets.sort();
-
-    let index_2 = packets
-        .iter()
-        .position(|p| *p == divider_2)
-        .context("Failed to find packet [[2]]")?
-        + 1;
-    let index_6 = packets
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            arena
+                .push(line)
+                .with_context(|| format!("Failed to parse packet at line {}", i + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let dividers = divider_args
         .iter()
-        .position(|p| *p == divider_6)
-        .context("Failed to find packet [[6]]")?
-        + 1;
-    let decoder_key = index_2 * index_6;
+        .map(|divider| {
+            arena
+                .push(divider)
+                .with_context(|| format!("Invalid divider: {divider}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let decoder_key = decoder_key(packets, &dividers, |a, b| arena.cmp(*a, *b));
     println!("{decoder_key}");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_json_round_trip() {
+        for line in INPUT.lines().filter(|line| !line.is_empty()) {
+            let packet = parser::packet(line).unwrap();
+            assert_eq!(packet.to_string(), line);
+
+            let json = serde_json::Value::from(&packet);
+            assert_eq!(json.to_string(), line);
+            let back = PacketData::try_from(&json).unwrap();
+            assert_eq!(back.to_string(), line);
+
+            let pretty: serde_json::Value = serde_json::from_str(&packet.pretty(4)).unwrap();
+            assert_eq!(pretty, json);
+        }
+    }
+
+    #[test]
+    fn explain_comparisons() {
+        let left = parser::packet("[[1],[2,3,4]]").unwrap();
+        let right = parser::packet("[[1],4]").unwrap();
+        let explanation = left.explain(&right);
+        assert_eq!(
+            explanation.to_string(),
+            "\
+- Compare [[1],[2,3,4]] vs [[1],4]
+  - Compare [1] vs [1]
+    - Compare 1 vs 1
+  - Compare [2,3,4] vs 4
+    - Mixed types; convert right to [4] and retry comparison
+    - Compare [2,3,4] vs [4]
+      - Compare 2 vs 4
+        - Left side is smaller, so inputs are in the right order
+"
+        );
+        assert_eq!(explanation.ordering, Ordering::Less);
+
+        for (left, right) in parse_pairs(INPUT).unwrap() {
+            assert_eq!(left.explain(&right).ordering, left.cmp(&right));
+        }
+    }
+
+    #[test]
+    fn arena_decoder_key() {
+        let pairs = parse_pairs(INPUT).unwrap();
+        let mut arena = PacketArena::default();
+        for (left, right) in &pairs {
+            let left_id = arena.push(&left.to_string()).unwrap();
+            let right_id = arena.push(&right.to_string()).unwrap();
+            assert_eq!(arena.cmp(left_id, right_id), left.cmp(right));
+            assert_eq!(arena.cmp(right_id, left_id), right.cmp(left));
+        }
+
+        // malformed packets are rejected with the same errors as the tree parser, and leave
+        // the arena untouched
+        let len = arena.nodes.len();
+        for input in [
+            "[1,[2]", "[1],[2]", "[1[2]]", "[,,1]", "[1-2]", "1", "  [1,x]",
+        ] {
+            let err = format!("{:#}", arena.push(input).unwrap_err());
+            let expected = format!("{:#}", parser::packet(input).unwrap_err());
+            assert_eq!(err, expected, "{input}");
+            assert_eq!(arena.nodes.len(), len);
+        }
+        let spaced = arena.push(" [[1],[2,[]]] ").unwrap();
+        let packed = arena.push("[[1],[2,[]]]").unwrap();
+        assert_eq!(arena.cmp(spaced, packed), Ordering::Equal);
+
+        // compare with a full sort
+        let mut packets = pairs
+            .into_iter()
+            .flat_map(|(left, right)| [left, right])
+            .collect_vec();
+        let dividers = ["[[2]]", "[[6]]", "[[4,4]]", "[]"].map(|d| parser::packet(d).unwrap());
+        let key = decoder_key(packets.clone(), &dividers, |a, b| a.cmp(b));
+        packets.extend(dividers.iter().cloned());
+        packets.sort();
+        let sorted_key: usize = dividers
+            .iter()
+            .map(|d| packets.iter().position(|p| p == d).unwrap() + 1)
+            .product();
+        assert_eq!(key, sorted_key);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = format!("{:#}", parse_pairs("[1,2]\n[3,[4,x]]\n").unwrap_err());
+        assert!(err.contains("line 2"), "{err}");
+        assert!(err.contains("column 6"), "{err}");
+        assert!(!err.contains("line 1"), "{err}");
+
+        // columns count the leading spaces
+        let err = format!("{:#}", parse_pairs("  [1,x]\n[2]\n").unwrap_err());
+        assert!(err.contains("line 1"), "{err}");
+        assert!(err.contains("column 5"), "{err}");
+
+        let err = format!(
+            "{:#}",
+            parse_pairs("[1]\n[2]\n[3]\n\n[4]\n[5]").unwrap_err()
+        );
+        assert!(err.contains("line 1"), "{err}");
+
+        let pairs = parse_pairs("\n\n[1]\n[2]\n\n\n\n[3]\n[[4]]  \n\n").unwrap();
+        assert_eq!(pairs.len(), 2);
+    }
+}
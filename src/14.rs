@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 use std::{
     cmp::{max, min},
@@ -46,8 +46,8 @@ impl Display for World {
 
 impl World {
     fn new(min_x: i32, min_y: i32, max_x: i32, max_y: i32) -> Self {
-        assert!(min_x < max_x);
-        assert!(min_y < max_y);
+        assert!(min_x <= max_x);
+        assert!(min_y <= max_y);
         let size_x = (max_x - min_x + 1) as usize;
         let size_y = (max_y - min_y + 1) as usize;
         World {
@@ -59,7 +59,7 @@ impl World {
         }
     }
 
-    fn get(&self, x: i32, y: i32) -> Option<&WorldCell> {
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
         if x < self.offset_x
             || y < self.offset_y
             || x >= self.offset_x + self.size_x as i32
@@ -69,243 +69,602 @@ impl World {
         }
         let i = (y - self.offset_y) as usize;
         let j = (x - self.offset_x) as usize;
-        self.raw_data.get(i * self.size_x + j)
+        Some(i * self.size_x + j)
+    }
+
+    fn get(&self, x: i32, y: i32) -> Option<&WorldCell> {
+        self.index(x, y).map(|i| &self.raw_data[i])
     }
 
     fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut WorldCell> {
-        if x < self.offset_x || y < self.offset_y {
-            return None;
+        self.index(x, y).map(|i| &mut self.raw_data[i])
+    }
+
+    /// Encode as a binary PPM image, each cell being drawn as a `scale` x `scale` square
+    fn to_ppm(&self, scale: usize) -> Vec<u8> {
+        let mut image =
+            format!("P6\n{} {}\n255\n", self.size_x * scale, self.size_y * scale).into_bytes();
+        for row in self.raw_data.chunks(self.size_x) {
+            for _ in 0..scale {
+                for cell in row {
+                    let pixel = match cell {
+                        Air => [16, 16, 32],
+                        Rock => [128, 128, 128],
+                        Sand => [230, 200, 90],
+                    };
+                    for _ in 0..scale {
+                        image.extend_from_slice(&pixel);
+                    }
+                }
+            }
         }
-        let i = (y - self.offset_y) as usize;
-        let j = (x - self.offset_x) as usize;
-        self.raw_data.get_mut(i * self.size_x + j)
+        image
     }
 
-    fn drop_sand(&mut self, x: i32, y: i32) -> Option<(i32, i32)> {
-        let (mut x, mut y) = (x, y);
+    /// Draw a rock path made of horizontal and vertical segments, as checked by `parse_paths`
+    fn draw_path(&mut self, path: &[(i32, i32)]) {
+        if let [(x, y)] = path {
+            *self.get_mut(*x, *y).unwrap() = Rock;
+        }
+        for (p0, p1) in path.iter().tuple_windows() {
+            if p0.0 != p1.0 {
+                assert!(p0.1 == p1.1);
+                let y = p0.1;
+                let start = min(p0.0, p1.0);
+                let last = max(p0.0, p1.0);
+                for x in start..=last {
+                    let cell = self.get_mut(x, y).unwrap();
+                    *cell = Rock;
+                }
+            } else {
+                let x = p0.0;
+                let start = min(p0.1, p1.1);
+                let last = max(p0.1, p1.1);
+                for y in start..=last {
+                    let cell = self.get_mut(x, y).unwrap();
+                    *cell = Rock;
+                }
+            }
+        }
+    }
+
+    /// Drop a unit of sand from the source. At each step, the sand moves to the first free
+    /// cell among `directions`. Returns where it comes to rest, or None if it falls off the
+    /// world or the source is blocked.
+    fn drop_sand(&mut self, source: (i32, i32), directions: &[(i32, i32)]) -> Option<(i32, i32)> {
+        let (mut x, mut y) = source;
         match self.get(x, y) {
             None => return None,
             Some(Air) => (),
             Some(Rock) | Some(Sand) => return None,
         }
-        loop {
-            // try down
-            let down = (x, y + 1);
-            match self.get(down.0, down.1) {
-                None => return None, // fell off the world
-                Some(Air) => {
-                    // fall down
-                    (x, y) = down;
-                    continue;
+        'falling: loop {
+            for &(dx, dy) in directions {
+                match self.get(x + dx, y + dy) {
+                    None => return None, // fell off the world
+                    Some(Air) => {
+                        (x, y) = (x + dx, y + dy);
+                        continue 'falling;
+                    }
+                    // blocked
+                    _ => (),
                 }
-                // blocked
-                _ => (),
             }
 
-            // try down left
-            let down_left = (x - 1, y + 1);
-            match self.get(down_left.0, down_left.1) {
-                None => return None, // fell off the world
-                Some(Air) => {
-                    // fall down left
-                    (x, y) = down_left;
-                    continue;
-                }
-                // blocked
-                _ => (),
-            }
+            // stopped falling
+            *self.get_mut(x, y).unwrap() = Sand;
+            return Some((x, y));
+        }
+    }
+}
 
-            // try down right
-            let down_right = (x + 1, y + 1);
-            match self.get(down_right.0, down_right.1) {
-                None => return None, // fell off the world
-                Some(Air) => {
-                    // fall down right
-                    (x, y) = down_right;
-                    continue;
+impl World {
+    /// Same as `drop_sand`, but resumes from the falling path of the previous unit of sand.
+    /// `path` must be empty for the first unit and then be left untouched between calls.
+    fn drop_sand_along(
+        &mut self,
+        path: &mut Vec<(i32, i32)>,
+        source: (i32, i32),
+        directions: &[(i32, i32)],
+    ) -> Option<(i32, i32)> {
+        // the previous unit only filled the last position of the path, so the next unit
+        // follows the same path up to the position just before
+        if path.is_empty() {
+            match self.get(source.0, source.1) {
+                Some(Air) => path.push(source),
+                _ => return None,
+            }
+        }
+        let (mut x, mut y) = *path.last().unwrap();
+        'falling: loop {
+            for &(dx, dy) in directions {
+                match self.get(x + dx, y + dy) {
+                    None => return None, // fell off the world
+                    Some(Air) => {
+                        (x, y) = (x + dx, y + dy);
+                        path.push((x, y));
+                        continue 'falling;
+                    }
+                    // blocked
+                    _ => (),
                 }
-                // blocked
-                _ => (),
             }
 
             // stopped falling
             *self.get_mut(x, y).unwrap() = Sand;
+            path.pop();
             return Some((x, y));
         }
     }
+
+    /// Fill with sand all the cells reachable from the source, row by row. When no sand can
+    /// fall off the world, this is where all the sand ends up, whatever the order of
+    /// `directions`. Every move must go down exactly one row, as checked by
+    /// `SandSim::build_world`, so that a row only depends on the row above. Returns the number
+    /// of cells filled.
+    fn fill_from(&mut self, source: (i32, i32), directions: &[(i32, i32)]) -> usize {
+        if !matches!(self.get(source.0, source.1), Some(Air)) {
+            return 0;
+        }
+        *self.get_mut(source.0, source.1).unwrap() = Sand;
+        let mut count = 1;
+        let max_x = self.offset_x + self.size_x as i32;
+        let max_y = self.offset_y + self.size_y as i32;
+        for y in source.1 + 1..max_y {
+            for x in self.offset_x..max_x {
+                if !matches!(self.get(x, y), Some(Air)) {
+                    continue;
+                }
+                if directions
+                    .iter()
+                    .any(|&(dx, dy)| matches!(self.get(x - dx, y - dy), Some(Sand)))
+                {
+                    *self.get_mut(x, y).unwrap() = Sand;
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
 }
 
-const INPUT: &str = include_str!("../data/14.txt");
+/// Parse the rock paths, one per line. Segments must be horizontal or vertical, and a single
+/// point is a single cell of rock. Errors give line and point numbers counted from 1.
+fn parse_paths(input: &str) -> Result<Vec<Vec<(i32, i32)>>> {
+    let mut paths = Vec::new();
+    for (nb, line) in input.lines().enumerate() {
+        let nb = nb + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let path = line
+            .split("->")
+            .enumerate()
+            .map(|(i, point)| {
+                parse_point(point)
+                    .with_context(|| format!("line {nb}, point {}: invalid point {point:?}", i + 1))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for (i, (p0, p1)) in path.iter().tuple_windows().enumerate() {
+            if p0.0 != p1.0 && p0.1 != p1.1 {
+                bail!(
+                    "line {nb}, points {}-{}: diagonal segment from {},{} to {},{}",
+                    i + 1,
+                    i + 2,
+                    p0.0,
+                    p0.1,
+                    p1.0,
+                    p1.1
+                );
+            }
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
 
-fn main() -> Result<()> {
-    // part 1
-    let mut polygons = Vec::new();
-    for line in INPUT.lines() {
-        let polygon = line
-            .split(" -> ")
-            .map(|s| s.split_once(',').unwrap())
-            .map(|(x, y)| (x.parse::<i32>().unwrap(), y.parse::<i32>().unwrap()))
-            .collect::<Vec<(i32, i32)>>();
-        polygons.push(polygon);
+/// Extent of the rocks of a cave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RockStats {
+    min: (i32, i32),
+    max: (i32, i32),
+    /// Number of distinct cells of rock
+    cells: usize,
+}
+
+impl RockStats {
+    fn of(paths: &[Vec<(i32, i32)>]) -> Option<Self> {
+        let points = || paths.iter().flatten();
+        let min = (points().map(|p| p.0).min()?, points().map(|p| p.1).min()?);
+        let max = (points().map(|p| p.0).max()?, points().map(|p| p.1).max()?);
+        let mut world = World::new(min.0, min.1, max.0, max.1);
+        // the world covers all the points, and `parse_paths` rejected diagonal segments
+        for path in paths {
+            world.draw_path(path);
+        }
+        let cells = world.raw_data.iter().filter(|c| matches!(c, Rock)).count();
+        Some(Self { min, max, cells })
     }
+}
 
-    // find world boundaries
-    let min_x = polygons
-        .iter()
-        .flatten()
-        .map(|(x, _)| x)
-        .min()
-        .unwrap()
-        .to_owned();
-    let min_y = polygons
-        .iter()
-        .flatten()
-        .map(|(_, y)| y)
-        .min()
-        .unwrap()
-        .to_owned();
-    let max_x = polygons
-        .iter()
-        .flatten()
-        .map(|(x, _)| x)
-        .max()
-        .unwrap()
-        .to_owned();
-    let max_y = polygons
-        .iter()
-        .flatten()
-        .map(|(_, y)| y)
-        .max()
-        .unwrap()
-        .to_owned();
-
-    // make sure drop point in inside
-    let min_y = min(0, min_y);
-    let max_y = max(0, max_y);
-    let min_x = min(500, min_x);
-    let max_x = max(500, max_x);
-
-    let mut world_map = World::new(min_x, min_y, max_x, max_y);
-
-    // draw rocks
-    for polygon in polygons {
-        for (p0, p1) in polygon.iter().tuple_windows() {
-            if p0.0 != p1.0 {
-                assert!(p0.1 == p1.1);
-                let y = p0.1;
-                let start = min(p0.0, p1.0);
-                let last = max(p0.0, p1.0);
-                for x in start..=last {
-                    let cell = world_map.get_mut(x, y).unwrap();
-                    *cell = Rock;
+impl Display for RockStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rocks: {} cells, x in {}..={}, y in {}..={}",
+            self.cells, self.min.0, self.max.0, self.min.1, self.max.1
+        )
+    }
+}
+
+/// What stops the sand below the rocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Floor {
+    /// Sand falls forever once it passes the lowest rock
+    None,
+    /// Infinite horizontal floor, the given distance below the lowest rock
+    Infinite(i32),
+    /// Box open at the top, whose walls are at `min_x` and `max_x` and floor at `max_y`.
+    /// Sand cannot get out of it.
+    Box { min_x: i32, max_x: i32, max_y: i32 },
+}
+
+/// How the sand is simulated. All the engines give the same results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Engine {
+    /// Each unit of sand falls from the source
+    Naive,
+    /// Each unit of sand resumes from the falling path of the previous one
+    Memoised,
+    /// Fill the reachable cells row by row, without simulating each unit. Only works when
+    /// there is a floor.
+    Fill,
+}
+
+impl TryFrom<&str> for Engine {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        match value {
+            "naive" => Ok(Engine::Naive),
+            "memo" => Ok(Engine::Memoised),
+            "fill" => Ok(Engine::Fill),
+            s => bail!("not a valid engine: {s}"),
+        }
+    }
+}
+
+/// Sand falling from a source into a cave of rocks
+#[derive(Debug, Clone)]
+struct SandSim {
+    source: (i32, i32),
+    floor: Floor,
+    /// (dx, dy) moves tried in order by each unit of sand, all to one of the 3 cells below
+    directions: Vec<(i32, i32)>,
+    engine: Engine,
+}
+
+impl Default for SandSim {
+    fn default() -> Self {
+        Self {
+            source: (500, 0),
+            floor: Floor::None,
+            directions: vec![(0, 1), (-1, 1), (1, 1)],
+            engine: Engine::Memoised,
+        }
+    }
+}
+
+impl SandSim {
+    /// World large enough to contain the rocks, the source and all the sand that can settle
+    fn build_world(&self, paths: &[Vec<(i32, i32)>]) -> Result<World> {
+        // larger moves would jump over rocks, the floor or the walls of the box
+        if self.directions.is_empty()
+            || self
+                .directions
+                .iter()
+                .any(|&(dx, dy)| dy != 1 || dx.abs() > 1)
+        {
+            bail!(
+                "sand must always fall to one of the 3 cells below: {:?}",
+                self.directions
+            );
+        }
+
+        let points = || paths.iter().flatten().chain([&self.source]);
+        let mut min_x = points().map(|p| p.0).min().unwrap();
+        let mut max_x = points().map(|p| p.0).max().unwrap();
+        let min_y = points().map(|p| p.1).min().unwrap();
+        let mut max_y = points().map(|p| p.1).max().unwrap();
+
+        let mut floor = None;
+        match self.floor {
+            Floor::None => (),
+            Floor::Infinite(depth) => {
+                if depth <= 0 {
+                    bail!("the floor must be below the rocks: {depth}");
                 }
-            } else {
-                assert!(p0.0 == p1.0);
-                let x = p0.0;
-                let start = min(p0.1, p1.1);
-                let last = max(p0.1, p1.1);
-                for y in start..=last {
-                    let cell = world_map.get_mut(x, y).unwrap();
-                    *cell = Rock;
+                max_y += depth;
+                // the pile cannot spread more than its height times the largest side move
+                let max_dx = self.directions.iter().map(|d| d.0.abs()).max().unwrap();
+                let spread = max_dx * (max_y - self.source.1);
+                min_x = min(min_x, self.source.0 - spread);
+                max_x = max(max_x, self.source.0 + spread);
+                floor = Some(vec![(min_x, max_y), (max_x, max_y)]);
+            }
+            Floor::Box {
+                min_x: left,
+                max_x: right,
+                max_y: bottom,
+            } => {
+                if bottom <= max_y || points().any(|p| p.0 <= left || p.0 >= right) {
+                    bail!(
+                        "the box must contain the source and the rocks: {:?}",
+                        self.floor
+                    );
                 }
+                min_x = left;
+                max_x = right;
+                max_y = bottom;
+                floor = Some(vec![
+                    (left, min_y),
+                    (left, bottom),
+                    (right, bottom),
+                    (right, min_y),
+                ]);
             }
         }
+
+        let mut world = World::new(min_x, min_y, max_x, max_y);
+        // the world covers all the points, `parse_paths` rejected diagonal rock segments and
+        // the floor is made of horizontal and vertical segments
+        for path in paths.iter().chain(&floor) {
+            world.draw_path(path);
+        }
+        Ok(world)
     }
 
-    // simulate sand falling
-    let mut count = 0;
-    while world_map.drop_sand(500, 0).is_some() {
-        count += 1;
+    /// Drop sand until it falls off the world or blocks the source. Returns the number of
+    /// units of sand at rest and the final world.
+    fn run(&self, paths: &[Vec<(i32, i32)>]) -> Result<(usize, World)> {
+        self.run_with_frames(paths, 0, |_, _| Ok(()))
     }
 
-    println!("{count}");
+    /// Same as `run`, calling `on_frame` with the number of units at rest and the world
+    /// before the first unit, every `every` units (never if 0) and at the end. The fill
+    /// engine does not simulate units, so it only has the first and last frames.
+    fn run_with_frames(
+        &self,
+        paths: &[Vec<(i32, i32)>],
+        every: usize,
+        mut on_frame: impl FnMut(usize, &World) -> Result<()>,
+    ) -> Result<(usize, World)> {
+        let mut world = self.build_world(paths)?;
+        on_frame(0, &world)?;
+        let mut count = 0;
+        let mut path = Vec::new();
+        loop {
+            let settled = match self.engine {
+                Engine::Naive => world.drop_sand(self.source, &self.directions),
+                Engine::Memoised => world.drop_sand_along(&mut path, self.source, &self.directions),
+                Engine::Fill => {
+                    if self.floor == Floor::None {
+                        bail!("the fill engine requires a floor");
+                    }
+                    count = world.fill_from(self.source, &self.directions);
+                    break;
+                }
+            };
+            if settled.is_none() {
+                break;
+            }
+            count += 1;
+            if every > 0 && count.is_multiple_of(every) {
+                on_frame(count, &world)?;
+            }
+        }
+        if count > 0 && (every == 0 || !count.is_multiple_of(every)) {
+            on_frame(count, &world)?;
+        }
+        Ok((count, world))
+    }
+}
 
-    let mut polygons = Vec::new();
-    for line in INPUT.lines() {
-        let polygon = line
-            .split(" -> ")
-            .map(|s| s.split_once(',').unwrap())
-            .map(|(x, y)| (x.parse::<i32>().unwrap(), y.parse::<i32>().unwrap()))
-            .collect::<Vec<(i32, i32)>>();
-        polygons.push(polygon);
+fn parse_point(s: &str) -> Result<(i32, i32)> {
+    let (x, y) = s
+        .split_once(',')
+        .with_context(|| format!("expected X,Y: {s}"))?;
+    Ok((x.trim().parse()?, y.trim().parse()?))
+}
+
+fn parse_floor(s: &str) -> Result<Floor> {
+    if s == "none" {
+        return Ok(Floor::None);
+    }
+    if let Some(bounds) = s.strip_prefix("box:") {
+        let (min_x, max_x, max_y) = bounds
+            .split(',')
+            .map(|v| v.parse::<i32>())
+            .collect::<std::result::Result<Vec<_>, _>>()?
+            .into_iter()
+            .collect_tuple()
+            .with_context(|| format!("expected box:MIN_X,MAX_X,MAX_Y: {s}"))?;
+        return Ok(Floor::Box {
+            min_x,
+            max_x,
+            max_y,
+        });
     }
+    Ok(Floor::Infinite(s.parse().with_context(|| {
+        format!("expected none, a depth or box:MIN_X,MAX_X,MAX_Y: {s}")
+    })?))
+}
 
-    // find world boundaries
-    let min_x = polygons
-        .iter()
-        .flatten()
-        .map(|(x, _)| x)
-        .min()
-        .unwrap()
-        .to_owned();
-    let min_y = polygons
-        .iter()
-        .flatten()
-        .map(|(_, y)| y)
-        .min()
-        .unwrap()
-        .to_owned();
-    let max_x = polygons
-        .iter()
-        .flatten()
-        .map(|(x, _)| x)
-        .max()
-        .unwrap()
-        .to_owned();
-    let max_y = polygons
-        .iter()
-        .flatten()
-        .map(|(_, y)| y)
-        .max()
-        .unwrap()
-        .to_owned();
-
-    // make sure drop point in inside
-    let min_y = min(0, min_y);
-    let max_y = max(0, max_y);
-    let min_x = min(500, min_x);
-    let max_x = max(500, max_x);
-
-    // add large enough bedrock
-    let min_x = min_x - 500;
-    let max_x = max_x + 500;
-    let max_y = max_y + 2;
-    polygons.push(vec![(min_x, max_y), (max_x, max_y)]);
-
-    let mut world_map = World::new(min_x, min_y, max_x, max_y);
-
-    // draw rocks
-    for polygon in polygons {
-        for (p0, p1) in polygon.iter().tuple_windows() {
-            if p0.0 != p1.0 {
-                assert!(p0.1 == p1.1);
-                let y = p0.1;
-                let start = min(p0.0, p1.0);
-                let last = max(p0.0, p1.0);
-                for x in start..=last {
-                    let cell = world_map.get_mut(x, y).unwrap();
-                    *cell = Rock;
-                }
-            } else {
-                assert!(p0.0 == p1.0);
-                let x = p0.0;
-                let start = min(p0.1, p1.1);
-                let last = max(p0.1, p1.1);
-                for y in start..=last {
-                    let cell = world_map.get_mut(x, y).unwrap();
-                    *cell = Rock;
-                }
+const INPUT: &str = include_str!("../data/14.txt");
+const IMAGE_SCALE: usize = 2;
+
+fn main() -> Result<()> {
+    // usage: 14 [--source X,Y] [--fall DX,DY/DX,DY/...] [--floor none|DEPTH|box:MIN_X,MAX_X,MAX_Y]
+    //           [--engine naive|memo|fill] [--show] [--stats]
+    //           [--every N] [--animate [--delay MS]] [--frames DIR]
+    // --animate streams a frame every N units of sand (default 100) to the terminal, --frames
+    // writes them as numbered PPM images
+    // by default, solve both parts with the puzzle rules. With --floor, run a single simulation.
+    let args = std::env::args().skip(1).collect_vec();
+    let option = |name: &str| -> Result<Option<&str>> {
+        match args.iter().position(|a| a == name) {
+            Some(i) => {
+                let value = args
+                    .get(i + 1)
+                    .with_context(|| format!("{name} requires a value"))?;
+                Ok(Some(value))
             }
+            None => Ok(None),
         }
+    };
+    let mut sim = SandSim::default();
+    if let Some(source) = option("--source")? {
+        sim.source = parse_point(source)?;
+    }
+    if let Some(fall) = option("--fall")? {
+        sim.directions = fall.split('/').map(parse_point).collect::<Result<_>>()?;
     }
+    if let Some(engine) = option("--engine")? {
+        sim.engine = Engine::try_from(engine)?;
+    }
+    let show = args.iter().any(|a| a == "--show");
+    let animate = args.iter().any(|a| a == "--animate");
+    let frames_dir = option("--frames")?.map(std::path::Path::new);
+    let every = match option("--every")? {
+        Some(every) => every.parse::<usize>()?,
+        None => 100,
+    };
+    let delay = match option("--delay")? {
+        Some(delay) => std::time::Duration::from_millis(delay.parse()?),
+        None => std::time::Duration::ZERO,
+    };
 
-    // simulate sand falling
-    let mut count = 0;
-    while world_map.drop_sand(500, 0).is_some() {
-        count += 1;
+    let paths = parse_paths(INPUT)?;
+    if args.iter().any(|a| a == "--stats") {
+        let stats = RockStats::of(&paths).context("no rocks")?;
+        println!("{stats}");
     }
 
-    println!("{count}");
+    let floors = match option("--floor")? {
+        Some(floor) => vec![parse_floor(floor)?],
+        // part 1 and part 2
+        None => vec![Floor::None, Floor::Infinite(2)],
+    };
+    for (run, floor) in floors.into_iter().enumerate() {
+        let sim = SandSim {
+            floor,
+            ..sim.clone()
+        };
+        let (count, world) = if animate || frames_dir.is_some() {
+            if animate {
+                print!("\x1b[2J");
+            }
+            let mut frame = 0;
+            sim.run_with_frames(&paths, every, |count, world| {
+                if animate {
+                    // redraw over the previous frame
+                    println!("\x1b[H{world}sand: {count}");
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                    std::thread::sleep(delay);
+                }
+                if let Some(dir) = frames_dir {
+                    let file = dir.join(format!("14_{}_{frame:05}.ppm", run + 1));
+                    std::fs::write(file, world.to_ppm(IMAGE_SCALE))?;
+                }
+                frame += 1;
+                Ok(())
+            })?
+        } else {
+            sim.run(&paths)?
+        };
+        if show {
+            println!("{world}");
+        }
+        println!("{count}");
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engines_agree() {
+        let paths = parse_paths(INPUT).unwrap();
+        let floors = [
+            Floor::None,
+            Floor::Infinite(2),
+            Floor::Box {
+                min_x: 400,
+                max_x: 600,
+                max_y: 180,
+            },
+        ];
+        let rules = [
+            SandSim::default().directions,
+            vec![(0, 1), (1, 1), (-1, 1)],
+            vec![(1, 1), (0, 1)],
+            vec![(-1, 1), (1, 1)],
+            vec![(0, 1)],
+        ];
+        for (floor, directions) in floors.into_iter().cartesian_product(rules) {
+            let run = |engine| {
+                let sim = SandSim {
+                    floor,
+                    directions: directions.clone(),
+                    engine,
+                    ..Default::default()
+                };
+                let (count, world) = sim.run(&paths).unwrap();
+                (count, world.to_string())
+            };
+            let naive = run(Engine::Naive);
+            assert_eq!(run(Engine::Memoised), naive, "{floor:?} {directions:?}");
+            if floor != Floor::None {
+                assert_eq!(run(Engine::Fill), naive, "{floor:?} {directions:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = format!(
+            "{:#}",
+            parse_paths("1,2 -> 1,5\n3,4 -> 3,x -> 5,4").unwrap_err()
+        );
+        assert!(err.contains("line 2, point 2: invalid"), "{err}");
+
+        let err = format!("{:#}", parse_paths("1,2 -> 1,5 -> 3,7").unwrap_err());
+        assert!(err.contains("line 1, points 2-3: diagonal"), "{err}");
+
+        let paths =
+            parse_paths("498,4 -> 498,6 -> 496,6\n503,4 -> 502,4 -> 502,9 -> 494,9\n").unwrap();
+        let stats = RockStats::of(&paths).unwrap();
+        assert_eq!(
+            stats,
+            RockStats {
+                min: (494, 4),
+                max: (503, 9),
+                cells: 20
+            }
+        );
+
+        let paths = parse_paths("5,5\n7,5 -> 7,6\n").unwrap();
+        let stats = RockStats::of(&paths).unwrap();
+        assert_eq!(
+            stats,
+            RockStats {
+                min: (5, 5),
+                max: (7, 6),
+                cells: 3
+            }
+        );
+    }
+}
@@ -1,71 +1,150 @@
 use anyhow::{Context, Result};
+use interval_set::{IntervalSet, Segment1D};
 use itertools::Itertools;
 use regex::Regex;
-use std::{
-    cmp::{max, min},
-    collections::HashSet,
-};
+use std::{collections::HashSet, ops::Range};
 
-#[derive(Debug)]
-struct Signal {
-    sensor: (i64, i64),
-    beacon: (i64, i64),
-}
+mod interval_set {
+    use std::cmp::{max, min};
 
-#[derive(Debug, Clone, Copy)]
-struct Segment1D(i64, i64);
+    /// Half-open range of integers [start, end)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Segment1D(pub i64, pub i64);
 
-impl Segment1D {
-    fn length(&self) -> u64 {
-        (self.1 - self.0) as u64
+    impl Segment1D {
+        pub fn length(&self) -> u64 {
+            (self.1 - self.0) as u64
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.1 <= self.0
+        }
     }
 
-    fn is_empty(&self) -> bool {
-        self.length() == 0
+    /// Set of integers stored as sorted, disjoint and non-adjacent segments
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct IntervalSet {
+        segments: Vec<Segment1D>,
     }
 
-    fn intersection(&self, other: &Self) -> Option<Segment1D> {
-        let left = max(self.0, other.0);
-        let right = min(self.1, other.1);
-        if left <= right {
-            Some(Segment1D(left, right))
-        } else {
-            None
+    impl IntervalSet {
+        pub fn segments(&self) -> &[Segment1D] {
+            &self.segments
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.segments.is_empty()
         }
-    }
 
-    fn difference(&self, other: &Self) -> Vec<Segment1D> {
-        let mut v = Vec::new();
-        match self.intersection(other) {
-            None => v.push(*self),
-            Some(inter) => {
-                if inter.0 != self.0 {
-                    v.push(Segment1D(self.0, inter.0));
+        /// Number of integers in the set
+        pub fn len(&self) -> u64 {
+            self.segments.iter().map(|s| s.length()).sum()
+        }
+
+        pub fn insert(&mut self, segment: Segment1D) {
+            if segment.is_empty() {
+                return;
+            }
+            // segments overlapping or touching the new one are merged with it
+            let start = self.segments.partition_point(|s| s.1 < segment.0);
+            let end = self.segments.partition_point(|s| s.0 <= segment.1);
+            let merged = if start < end {
+                Segment1D(
+                    min(segment.0, self.segments[start].0),
+                    max(segment.1, self.segments[end - 1].1),
+                )
+            } else {
+                segment
+            };
+            self.segments.splice(start..end, [merged]);
+        }
+
+        pub fn remove(&mut self, segment: Segment1D) {
+            if segment.is_empty() {
+                return;
+            }
+            // only the ends of the first and last overlapping segments are kept
+            let start = self.segments.partition_point(|s| s.1 <= segment.0);
+            let end = self.segments.partition_point(|s| s.0 < segment.1);
+            let mut kept = Vec::new();
+            if start < end {
+                let first = self.segments[start];
+                let last = self.segments[end - 1];
+                if first.0 < segment.0 {
+                    kept.push(Segment1D(first.0, segment.0));
                 }
-                if inter.1 != self.1 {
-                    v.push(Segment1D(inter.1, self.1));
+                if last.1 > segment.1 {
+                    kept.push(Segment1D(segment.1, last.1));
                 }
             }
-        };
-        v
+            self.segments.splice(start..end, kept);
+        }
+
+        /// Segments of `bounds` that are not in the set, in increasing order
+        pub fn gaps(&self, bounds: Segment1D) -> impl Iterator<Item = Segment1D> + '_ {
+            let mut pos = bounds.0;
+            self.segments
+                .iter()
+                .map(Some)
+                .chain([None])
+                .filter_map(move |segment| {
+                    let gap = match segment {
+                        Some(s) => Segment1D(pos, min(s.0, bounds.1)),
+                        None => Segment1D(pos, bounds.1),
+                    };
+                    if let Some(s) = segment {
+                        pos = max(pos, s.1);
+                    }
+                    (!gap.is_empty()).then_some(gap)
+                })
+        }
+    }
+
+    impl FromIterator<Segment1D> for IntervalSet {
+        /// Sort the segments before inserting them, so each insertion only merges with the
+        /// last segment of the set. The whole construction is O(n log n).
+        fn from_iter<I: IntoIterator<Item = Segment1D>>(iter: I) -> Self {
+            let mut sorted = iter.into_iter().collect::<Vec<_>>();
+            sorted.sort_unstable_by_key(|s| s.0);
+            let mut set = Self {
+                segments: Vec::with_capacity(sorted.len()),
+            };
+            for segment in sorted {
+                set.insert(segment);
+            }
+            set
+        }
     }
 }
 
+#[derive(Debug)]
+struct Signal {
+    sensor: (i64, i64),
+    beacon: (i64, i64),
+}
+
 impl Signal {
     fn dist(&self) -> i64 {
         (self.beacon.0 - self.sensor.0).abs() + (self.beacon.1 - self.sensor.1).abs()
     }
-}
 
-const INPUT: &str = include_str!("../data/15.txt");
+    /// Positions of the row `y` scanned by the sensor
+    fn row_coverage(&self, y: i64) -> Option<Segment1D> {
+        // check if the circle (sensor, signal.dist) intersect with the row
+        let d = self.dist() - (self.sensor.1 - y).abs();
+        if d < 0 {
+            return None; // too far from the row
+        }
+        Some(Segment1D(self.sensor.0 - d, self.sensor.0 + d + 1))
+    }
+}
 
-fn main() -> Result<()> {
-    // part 1
+fn parse_signals(input: &str) -> Result<Vec<Signal>> {
     let re = Regex::new(
         r"Sensor at x=(?P<sensor_x>-?\d+), y=(?P<sensor_y>-?\d+): closest beacon is at x=(?P<beacon_x>-?\d+), y=(?P<beacon_y>-?\d+)",
     )?;
     let mut signals = Vec::new();
-    for line in INPUT.lines() {
+    for line in input.lines() {
         let caps = re
             .captures(line)
             .with_context(|| format!("Failed to parse line: {}", &line))?;
@@ -78,114 +157,121 @@ fn main() -> Result<()> {
             beacon: (beacon_x, beacon_y),
         });
     }
+    Ok(signals)
+}
 
-    // find segments scanned on the row y=2000000
-    const TARGET_ROW_Y: i64 = 2000000;
-    let mut segments: Vec<Segment1D> = Vec::new();
-    for signal in signals.iter() {
-        // check if the circle (sensor, signal.dist) intersect with target row
-        let d = signal.dist() - (signal.sensor.1 - TARGET_ROW_Y).abs();
-        if d < 0 {
-            continue; // too far from target row
-        }
-        segments.push(Segment1D(signal.sensor.0 - d, signal.sensor.0 + d + 1));
+/// Positions of the row `y` scanned by any sensor
+fn scanned_row(signals: &[Signal], y: i64) -> IntervalSet {
+    signals.iter().filter_map(|s| s.row_coverage(y)).collect()
+}
+
+/// Positions scanned on each row of the range
+fn scan_rows(
+    signals: &[Signal],
+    rows: Range<i64>,
+) -> impl Iterator<Item = (i64, IntervalSet)> + '_ {
+    rows.map(|y| (y, scanned_row(signals, y)))
+}
+
+/// Positions of the row `y` where there cannot be a beacon: scanned, but not a known beacon
+fn no_beacon_row(signals: &[Signal], y: i64) -> IntervalSet {
+    let mut scanned = scanned_row(signals, y);
+    let beacons = signals
+        .iter()
+        .map(|s| s.beacon)
+        .filter(|b| b.1 == y)
+        .collect::<HashSet<_>>();
+    for beacon in beacons {
+        scanned.remove(Segment1D(beacon.0, beacon.0 + 1));
     }
+    scanned
+}
 
-    // rewrite segments list so they are all disjoints
-    segments = {
-        let mut disjoint_segments = Vec::new();
-        for segment in segments {
-            let mut tmp = vec![segment];
-            for added in disjoint_segments.iter() {
-                tmp = tmp
-                    .into_iter()
-                    .flat_map(|s| s.difference(added))
-                    .collect_vec();
-            }
-            disjoint_segments.extend(tmp);
-        }
-        disjoint_segments
-    };
-
-    // list beacons on target row
-    let mut beacons_on_target_row = HashSet::<(i64, i64)>::new();
-    for signal in signals.iter() {
-        if signal.beacon.1 == TARGET_ROW_Y {
-            beacons_on_target_row.insert(signal.beacon);
+fn parse_rows(s: &str) -> Result<Range<i64>> {
+    match s.split_once("..") {
+        Some((min, max)) => Ok(min.parse()?..max.parse()?),
+        None => {
+            let y = s.parse()?;
+            Ok(y..y + 1)
         }
     }
+}
 
-    // remove beacon from segments
-    segments = {
-        let mut no_beacon_segment = Vec::new();
-        for segment in segments {
-            let mut tmp = vec![segment];
-            for beacon in beacons_on_target_row.iter() {
-                tmp = tmp
-                    .into_iter()
-                    .flat_map(|s| s.difference(&Segment1D(beacon.0, beacon.0 + 1)))
-                    .collect_vec();
+const INPUT: &str = include_str!("../data/15.txt");
+const TARGET_ROW_Y: i64 = 2000000;
+const SEARCH_MAX: i64 = 4000000;
+
+fn main() -> Result<()> {
+    let signals = parse_signals(INPUT)?;
+
+    // usage: 15 [Y | MIN_Y..MAX_Y]
+    // print the positions where there cannot be a beacon, and the gaps within the search
+    // area, on each of the given rows
+    if let Some(rows) = std::env::args().nth(1) {
+        let rows = parse_rows(&rows)?;
+        let bounds = Segment1D(0, SEARCH_MAX + 1);
+        let format_segments = |segments: &mut dyn Iterator<Item = Segment1D>| {
+            segments.map(|s| format!("{}..{}", s.0, s.1)).join(" ")
+        };
+        for y in rows {
+            let no_beacon = no_beacon_row(&signals, y);
+            if no_beacon.is_empty() {
+                println!("{y}: not scanned");
+                continue;
             }
-            no_beacon_segment.extend(tmp);
+            println!(
+                "{y}: {} no beacon in [{}], gaps: [{}]",
+                no_beacon.len(),
+                format_segments(&mut no_beacon.segments().iter().copied()),
+                format_segments(&mut scanned_row(&signals, y).gaps(bounds)),
+            );
         }
-        no_beacon_segment
-    };
+        return Ok(());
+    }
 
-    let no_beacon_count: u64 = segments.iter().map(|s| s.length()).sum();
+    // part 1
+    let no_beacon_count = no_beacon_row(&signals, TARGET_ROW_Y).len();
     println!("{no_beacon_count}");
 
     // part 2
-    let re = Regex::new(
-        r"Sensor at x=(?P<sensor_x>-?\d+), y=(?P<sensor_y>-?\d+): closest beacon is at x=(?P<beacon_x>-?\d+), y=(?P<beacon_y>-?\d+)",
-    )?;
-    let mut signals = Vec::new();
-    for line in INPUT.lines() {
-        let caps = re
-            .captures(line)
-            .with_context(|| format!("Failed to parse line: {}", &line))?;
-        let sensor_x = caps["sensor_x"].parse::<i64>()?;
-        let sensor_y = caps["sensor_y"].parse::<i64>()?;
-        let beacon_x = caps["beacon_x"].parse::<i64>()?;
-        let beacon_y = caps["beacon_y"].parse::<i64>()?;
-        signals.push(Signal {
-            sensor: (sensor_x, sensor_y),
-            beacon: (beacon_x, beacon_y),
-        });
-    }
-
     // find the only position that has not been scanned
-    let mut distress_beacon = None;
-    for target_row_y in 0..4000000 {
-        let mut unscanned_x = vec![Segment1D(0, 4000000)];
-        for signal in signals.iter() {
-            // check if the circle (sensor, signal.dist) intersect with target row
-            let d = signal.dist() - (signal.sensor.1 - target_row_y).abs();
-            if d < 0 {
-                continue; // too far from target row
-            }
-            let scanned = Segment1D(signal.sensor.0 - d, signal.sensor.0 + d + 1);
-            unscanned_x = unscanned_x
-                .into_iter()
-                .flat_map(|s| s.difference(&scanned))
-                .filter(|s| !s.is_empty())
-                .collect_vec();
-            if unscanned_x.is_empty() {
-                break; // everything has been scanned
-            }
-        }
-
-        if !unscanned_x.is_empty() {
-            // found something
-            assert!(unscanned_x.len() == 1);
-            assert!(unscanned_x[0].length() == 1);
-            distress_beacon = Some(Segment1D(unscanned_x[0].0, target_row_y));
-            break; // assume to be the only 1
-        }
-    }
-
-    let distress_beacon = distress_beacon.context("did not find distress beacon")?;
-    let tuning_frequency = distress_beacon.0 * 4000000 + distress_beacon.1;
+    let bounds = Segment1D(0, SEARCH_MAX + 1);
+    let (y, x) = scan_rows(&signals, 0..SEARCH_MAX + 1)
+        .find_map(|(y, scanned)| scanned.gaps(bounds).next().map(|gap| (y, gap.0)))
+        .context("did not find distress beacon")?;
+    let tuning_frequency = x * 4000000 + y;
     println!("{tuning_frequency}");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_set() {
+        let mut set: IntervalSet = [Segment1D(5, 8), Segment1D(0, 2), Segment1D(1, 3)]
+            .into_iter()
+            .collect();
+        assert_eq!(set.segments(), [Segment1D(0, 3), Segment1D(5, 8)]);
+        assert_eq!(set.len(), 6);
+
+        set.insert(Segment1D(3, 4));
+        assert_eq!(set.segments(), [Segment1D(0, 4), Segment1D(5, 8)]);
+        set.insert(Segment1D(4, 5));
+        assert_eq!(set.segments(), [Segment1D(0, 8)]);
+
+        set.remove(Segment1D(2, 3));
+        set.remove(Segment1D(6, 10));
+        assert_eq!(set.segments(), [Segment1D(0, 2), Segment1D(3, 6)]);
+        assert_eq!(
+            set.gaps(Segment1D(-1, 10)).collect_vec(),
+            [Segment1D(-1, 0), Segment1D(2, 3), Segment1D(6, 10)]
+        );
+        assert_eq!(set.gaps(Segment1D(1, 5)).collect_vec(), [Segment1D(2, 3)]);
+
+        set.remove(Segment1D(-5, 20));
+        assert!(set.is_empty());
+    }
+}
@@ -66,7 +66,7 @@ fn depth_first(
     }
 
     // sort candidates so we explore highest gain first
-    candidates.sort_by(|c1, c2| c2.gain.cmp(&c1.gain));
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.gain));
 
     let mut best_pressure = max(curr_pressure, best_pressure);
 
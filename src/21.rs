@@ -119,7 +119,7 @@ mod parser {
         Ok((input, (name, op)))
     }
 
-    pub fn monkey_job(input: &str) -> Result<(&str, Monkey)> {
+    pub fn monkey_job(input: &str) -> Result<(&str, Monkey<'_>)> {
         let (_, (name, job)) = complete(_monkey_job::<nom::error::VerboseError<&str>>)(input)
             .finish()
             .map_err(|e| anyhow!(convert_error(input, e)))?;
@@ -258,92 +258,6 @@ const NEIGHBOR_FACES: [NeighborFace; 6] = [
     },
 ];
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    impl Direction {
-        fn opposite(&self) -> Direction {
-            match self {
-                Right => Left,
-                Down => Up,
-                Left => Right,
-                Up => Down,
-            }
-        }
-    }
-
-    #[test]
-    fn face_connection() {
-        // make sure tile layout is correct
-        for (n, (i, j)) in FACE_COORDS.iter().enumerate() {
-            assert_eq!(TILES[*i][*j], Some(n));
-        }
-
-        // make sure all faces are connected from all sides
-        let mut connected = [[false; 4]; 6];
-        for face in NEIGHBOR_FACES {
-            connected[face.right.0][face.right.1 as usize] = true;
-            connected[face.down.0][face.down.1 as usize] = true;
-            connected[face.left.0][face.left.1 as usize] = true;
-            connected[face.up.0][face.up.1 as usize] = true;
-        }
-        println!("{connected:?}\n");
-        assert!(connected.iter().flatten().all(|b| *b));
-
-        // make sure faces connections are consistent
-        for (id, face) in NEIGHBOR_FACES.iter().enumerate() {
-            println!("{id}: {face:?}");
-            println!("right: {:?}", NEIGHBOR_FACES[face.right.0]);
-            assert_eq!(
-                id,
-                NEIGHBOR_FACES[face.right.0].get(face.right.1.opposite()).0
-            );
-            assert_eq!(
-                Right,
-                NEIGHBOR_FACES[face.right.0]
-                    .get(face.right.1.opposite())
-                    .1
-                    .opposite()
-            );
-            println!("down: {:?}", NEIGHBOR_FACES[face.down.0]);
-            assert_eq!(
-                id,
-                NEIGHBOR_FACES[face.down.0].get(face.down.1.opposite()).0
-            );
-            assert_eq!(
-                Down,
-                NEIGHBOR_FACES[face.down.0]
-                    .get(face.down.1.opposite())
-                    .1
-                    .opposite()
-            );
-            println!("left: {:?}", NEIGHBOR_FACES[face.left.0]);
-            assert_eq!(
-                id,
-                NEIGHBOR_FACES[face.left.0].get(face.left.1.opposite()).0
-            );
-            assert_eq!(
-                Left,
-                NEIGHBOR_FACES[face.left.0]
-                    .get(face.left.1.opposite())
-                    .1
-                    .opposite()
-            );
-            println!("up: {:?}", NEIGHBOR_FACES[face.up.0]);
-            assert_eq!(id, NEIGHBOR_FACES[face.up.0].get(face.up.1.opposite()).0);
-            assert_eq!(
-                Up,
-                NEIGHBOR_FACES[face.up.0]
-                    .get(face.up.1.opposite())
-                    .1
-                    .opposite()
-            );
-            println!();
-        }
-    }
-}
-
 fn execute(world: &World, p: Position, instr: Instruction) -> Position {
     match instr {
         Forward(n) => {
@@ -558,3 +472,89 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Direction {
+        fn opposite(&self) -> Direction {
+            match self {
+                Right => Left,
+                Down => Up,
+                Left => Right,
+                Up => Down,
+            }
+        }
+    }
+
+    #[test]
+    fn face_connection() {
+        // make sure tile layout is correct
+        for (n, (i, j)) in FACE_COORDS.iter().enumerate() {
+            assert_eq!(TILES[*i][*j], Some(n));
+        }
+
+        // make sure all faces are connected from all sides
+        let mut connected = [[false; 4]; 6];
+        for face in NEIGHBOR_FACES {
+            connected[face.right.0][face.right.1 as usize] = true;
+            connected[face.down.0][face.down.1 as usize] = true;
+            connected[face.left.0][face.left.1 as usize] = true;
+            connected[face.up.0][face.up.1 as usize] = true;
+        }
+        println!("{connected:?}\n");
+        assert!(connected.iter().flatten().all(|b| *b));
+
+        // make sure faces connections are consistent
+        for (id, face) in NEIGHBOR_FACES.iter().enumerate() {
+            println!("{id}: {face:?}");
+            println!("right: {:?}", NEIGHBOR_FACES[face.right.0]);
+            assert_eq!(
+                id,
+                NEIGHBOR_FACES[face.right.0].get(face.right.1.opposite()).0
+            );
+            assert_eq!(
+                Right,
+                NEIGHBOR_FACES[face.right.0]
+                    .get(face.right.1.opposite())
+                    .1
+                    .opposite()
+            );
+            println!("down: {:?}", NEIGHBOR_FACES[face.down.0]);
+            assert_eq!(
+                id,
+                NEIGHBOR_FACES[face.down.0].get(face.down.1.opposite()).0
+            );
+            assert_eq!(
+                Down,
+                NEIGHBOR_FACES[face.down.0]
+                    .get(face.down.1.opposite())
+                    .1
+                    .opposite()
+            );
+            println!("left: {:?}", NEIGHBOR_FACES[face.left.0]);
+            assert_eq!(
+                id,
+                NEIGHBOR_FACES[face.left.0].get(face.left.1.opposite()).0
+            );
+            assert_eq!(
+                Left,
+                NEIGHBOR_FACES[face.left.0]
+                    .get(face.left.1.opposite())
+                    .1
+                    .opposite()
+            );
+            println!("up: {:?}", NEIGHBOR_FACES[face.up.0]);
+            assert_eq!(id, NEIGHBOR_FACES[face.up.0].get(face.up.1.opposite()).0);
+            assert_eq!(
+                Up,
+                NEIGHBOR_FACES[face.up.0]
+                    .get(face.up.1.opposite())
+                    .1
+                    .opposite()
+            );
+            println!();
+        }
+    }
+}
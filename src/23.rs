@@ -104,45 +104,6 @@ impl World {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn world_resize() {
-        let world_str = ["###..", "#####", ".....", "#...#", "....#"];
-
-        let mut world = World::new(0, 0, 0, 0);
-        for (x, line) in world_str.iter().enumerate() {
-            for (y, c) in line.chars().enumerate() {
-                *world.get_mut(x as i32, y as i32) = match c {
-                    '#' => Elf,
-                    '.' => Empty,
-                    _ => panic!("Unknown char '{c}'"),
-                };
-            }
-        }
-        *world.get_mut(7, 7) = Elf;
-        *world.get_mut(10, 3) = Elf;
-        *world.get_mut(-1, -1) = Elf;
-        *world.get_mut(-5, 0) = Elf;
-        println!("{world}");
-        assert_eq!(*world.get(0, 0), Elf);
-        assert_eq!(*world.get(1, 0), Elf);
-        assert_eq!(*world.get(2, 0), Empty);
-        assert_eq!(*world.get(3, 0), Elf);
-        assert_eq!(*world.get(4, 0), Empty);
-        assert_eq!(*world.get(4, 4), Elf);
-        assert_eq!(*world.get(7, 7), Elf);
-        assert_eq!(*world.get(10, 3), Elf);
-        assert_eq!(*world.get(-1, -1), Elf);
-        assert_eq!(*world.get(-5, 0), Elf);
-        assert_eq!(*world.get(-1, 0), Empty);
-        assert_eq!(*world.get(0, -1), Empty);
-        assert_eq!(*world.get(-10, -10), Empty);
-    }
-}
-
 type Coord = (i32, i32);
 
 const NORTH: Coord = (-1, 0);
@@ -296,3 +257,42 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_resize() {
+        let world_str = ["###..", "#####", ".....", "#...#", "....#"];
+
+        let mut world = World::new(0, 0, 0, 0);
+        for (x, line) in world_str.iter().enumerate() {
+            for (y, c) in line.chars().enumerate() {
+                *world.get_mut(x as i32, y as i32) = match c {
+                    '#' => Elf,
+                    '.' => Empty,
+                    _ => panic!("Unknown char '{c}'"),
+                };
+            }
+        }
+        *world.get_mut(7, 7) = Elf;
+        *world.get_mut(10, 3) = Elf;
+        *world.get_mut(-1, -1) = Elf;
+        *world.get_mut(-5, 0) = Elf;
+        println!("{world}");
+        assert_eq!(*world.get(0, 0), Elf);
+        assert_eq!(*world.get(1, 0), Elf);
+        assert_eq!(*world.get(2, 0), Empty);
+        assert_eq!(*world.get(3, 0), Elf);
+        assert_eq!(*world.get(4, 0), Empty);
+        assert_eq!(*world.get(4, 4), Elf);
+        assert_eq!(*world.get(7, 7), Elf);
+        assert_eq!(*world.get(10, 3), Elf);
+        assert_eq!(*world.get(-1, -1), Elf);
+        assert_eq!(*world.get(-5, 0), Elf);
+        assert_eq!(*world.get(-1, 0), Empty);
+        assert_eq!(*world.get(0, -1), Empty);
+        assert_eq!(*world.get(-10, -10), Empty);
+    }
+}
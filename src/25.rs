@@ -22,7 +22,7 @@ fn main() {
     let mut val = sum;
     while val != 0 {
         let r = val % 5;
-        val = val / 5;
+        val /= 5;
         let c = match r {
             0 => '0',
             1 => '1',